@@ -0,0 +1,80 @@
+use std::time::Instant;
+
+use rand::Rng;
+
+use rust3d::math::Vec3;
+use rust3d::render::objects::{Camera, Diamond, Ray, Scene, Sphere};
+
+const RAYS: usize = 100_000;
+const MAX_LINEAR_SHAPES: usize = 10_000;
+
+fn random_scene(count: usize) -> Scene {
+    let mut rng = rand::thread_rng();
+    let camera = Camera::new(Vec3::new(0.0, 0.0, -500.0), Diamond::default());
+    let mut scene = Scene::new(camera);
+
+    // keep the density roughly constant so that rays hit a similar amount of spheres
+    let side = 100.0 * (count as f32).cbrt();
+
+    for _ in 0..count {
+        let center = Vec3::new(
+            rng.gen_range(-side..side),
+            rng.gen_range(-side..side),
+            rng.gen_range(-side..side),
+        );
+        scene.add_object(Box::new(Sphere::new(center, rng.gen_range(1.0..10.0))));
+    }
+
+    scene
+}
+
+fn random_rays(count: usize) -> Vec<Ray> {
+    let mut rng = rand::thread_rng();
+
+    (0..count).map(|_| {
+        let direction = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        Ray::new(Vec3::new(0.0, 0.0, 0.0), direction)
+    }).collect()
+}
+
+fn trace(scene: &Scene, rays: &[Ray]) -> (usize, f64) {
+    let t_start = Instant::now();
+    let hits = rays.iter().filter(|ray| scene.intersect(ray).is_some()).count();
+    (hits, t_start.elapsed().as_secs_f64() * 1000.0)
+}
+
+fn main() {
+    let rays = random_rays(RAYS);
+
+    println!("{:>10} {:>12} {:>14} {:>14} {:>10}", "shapes", "build (ms)", "bvh (Mray/s)", "linear (Mray/s)", "hits");
+
+    for count in [100, 1_000, 10_000, 100_000] {
+        let mut scene = random_scene(count);
+
+        let linear = if count <= MAX_LINEAR_SHAPES {
+            let (_, ms) = trace(&scene, &rays);
+            format!("{:.3}", RAYS as f64 / ms / 1000.0)
+        } else {
+            "-".to_string()
+        };
+
+        let t_start = Instant::now();
+        scene.build_bvh();
+        let build_ms = t_start.elapsed().as_secs_f64() * 1000.0;
+
+        let (hits, ms) = trace(&scene, &rays);
+
+        println!(
+            "{:>10} {:>12.1} {:>14.3} {:>14} {:>10}",
+            count,
+            build_ms,
+            RAYS as f64 / ms / 1000.0,
+            linear,
+            hits,
+        );
+    }
+}
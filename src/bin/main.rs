@@ -1,30 +1,53 @@
+#[cfg(feature = "sdl")]
 use sdl2::event::Event;
+#[cfg(feature = "sdl")]
 use sdl2::keyboard::Keycode;
+use std::sync::Arc;
+#[cfg(feature = "sdl")]
 use std::time::Duration;
 
 use rust3d::render::{objects, objects::Camera};
-use rust3d::render::{render, Display};
+use rust3d::render::{render, Framebuffer, RenderSettings, ToneMapping};
+#[cfg(feature = "sdl")]
+use rust3d::render::{render_progressive, Accumulator, Display};
+use rust3d::render::environment::Environment;
+use rust3d::render::image;
+use rust3d::render::tonemap::Operator;
+use rust3d::render::material::Phong;
 use rust3d::math::Vec3;
+#[cfg(feature = "sdl")]
+use rust3d::math::Quat;
+
+/// Prints `message` and stops the program with an error code.
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+/// Value of the `name` command line option, e.g. `--output <path>`, if any.
+fn option(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return Some(args.next().unwrap_or_else(|| exit_with_error(&format!("{} expects a value", name))));
+        }
+    }
+
+    None
+}
 
 pub fn main() {
     let width = 800;
     let height = 600;
 
-    let ratio = height as f32 / width as f32;
-    let screen_width = width as f32 / 10.0;
-    let screen_height = screen_width * ratio;
-    let focal = 500.0;
-
-
-    let screen_z = 0.0;
-    let origin = Vec3::new(0.0, 0.0, screen_z - focal);
-    let screen = objects::Diamond::new(
-        Vec3::new(origin.x, origin.y, screen_z),
-        Vec3::new(screen_width, 0.0, 0.0),
-        Vec3::new(0.0, screen_height, 0.0)
+    let camera = Camera::look_at(
+        Vec3::new(0.0, 0.0, -500.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        7.0,
+        width as f32 / height as f32,
     );
-
-    let camera = Camera::new(origin, screen);
     println!("Camera: {:?}", camera);
 
     let c = 10.0;
@@ -40,19 +63,59 @@ pub fn main() {
 
     let mut scene = objects::Scene::new(camera);
     scene.add_object(Box::new(rect));
-    scene.add_object(Box::new(sphere));
+    scene.add_object_with_material(
+        Box::new(sphere),
+        Arc::new(Phong::new(objects::Color::new(0.3, 0.5, 1.0)).with_specular(objects::Color::new(1.0, 1.0, 1.0), 50.0)),
+    );
 
     let quad = objects::Quad::iso(Vec3::new(30.0, -20.0, 80.0), 15.0);
-    scene.add_object(Box::new(quad));
+    scene.add_object_with_material(Box::new(quad), Arc::new(Phong::new(objects::Color::new(0.4, 1.0, 0.4))));
 
-    let light_a = objects::Light::new(
+    let light_a = objects::Light::sphere(
         Vec3::new(40.0, 40.0, -50.0),
         5.0,
-        objects::Color::new(1.0, 0.0, 0.0)
+        objects::Color::new(1.0, 0.0, 0.0),
+        250.0,
     );
 
     scene.add_light(light_a);
+    scene.environment = Environment::gradient(
+        objects::Color::new(0.1, 0.2, 0.4),
+        objects::Color::new(0.3, 0.3, 0.3),
+        objects::Color::new(0.1, 0.1, 0.1),
+    );
+
+    let settings = RenderSettings::default();
+
+    // `--exposure <stops>` brightens or darkens the image
+    let exposure = option("--exposure").map_or(0.0, |stops| {
+        stops.parse().unwrap_or_else(|_| exit_with_error("--exposure expects a number"))
+    });
+    let tone_mapping = ToneMapping::new(Operator::Aces).with_exposure(exposure);
+
+    if let Some(path) = option("--output") {
+        // offline render: no window, a single frame written to disk
+        let mut framebuffer = Framebuffer::new(width, height);
+        render(&mut scene, &settings, &mut framebuffer);
+        if let Err(error) = image::save(&framebuffer, &path, &tone_mapping) {
+            exit_with_error(&format!("could not save {}: {}", path, error));
+        }
+        println!("Saved frame to {}", path);
+        return;
+    }
 
+    #[cfg(not(feature = "sdl"))]
+    {
+        exit_with_error("built without a window, use --output <path> to render to a file");
+    }
+
+    #[cfg(feature = "sdl")]
+    run_window(scene, &settings, tone_mapping, width, height);
+}
+
+/// Interactive render: the image keeps on refining while the camera, moved with the keyboard, stands still.
+#[cfg(feature = "sdl")]
+fn run_window(mut scene: objects::Scene, settings: &RenderSettings, tone_mapping: ToneMapping, width: u32, height: u32) {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
@@ -62,12 +125,15 @@ pub fn main() {
     .unwrap();
 
     let canvas = window.into_canvas().build().unwrap();
-    let mut display = Display::new(canvas, width, height);
+    let mut display = Display::new(canvas, width, height).with_tone_mapping(tone_mapping);
 
     // display.canvas.set_draw_color(Color::RGB(0, 255, 255));
     // display.canvas.clear();
     // display.canvas.present();
 
+    // the image keeps on refining while the camera stands still
+    let mut accumulator = Accumulator::new(width, height);
+
     let mut frame_num = 0;
     let t_start = std::time::Instant::now();
 
@@ -77,7 +143,7 @@ pub fn main() {
         // display.canvas.set_draw_color(Color::RGB(255, 255, 255));
         // display.canvas.draw_point(Point::new(100, 100)).unwrap();
 
-        render(&mut scene, &mut display);
+        render_progressive(&mut scene, settings, &mut accumulator, &mut display);
         let t_elapsed = t_start.elapsed();
         let fps = frame_num as f64 / t_elapsed.as_secs_f64();
 
@@ -85,6 +151,8 @@ pub fn main() {
 
         let a = std::f32::consts::PI / 180.0;
         let d = 10.0;
+        let pitch = |angle: f32| Quat::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), angle);
+        let yaw = |angle: f32| Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), angle);
 
         for event in event_pump.poll_iter() {
             match event {
@@ -109,19 +177,19 @@ pub fn main() {
                     println!("Camera position: {:?}", scene.camera.position);
                 },
                 Event::KeyDown { keycode: Some(Keycode::Up), .. } => {
-                    scene.camera.rotate(a, 0.0, 0.0);
+                    scene.camera.rotate_quat(&pitch(a));
                     println!("Camera position: {:?}", scene.camera.position);
                 },
                 Event::KeyDown { keycode: Some(Keycode::Down), .. } => {
-                    scene.camera.rotate(-a, 0.0, 0.0);
+                    scene.camera.rotate_quat(&pitch(-a));
                     println!("Camera position: {:?}", scene.camera.position);
                 },
                 Event::KeyDown { keycode: Some(Keycode::Left), .. } => {
-                    scene.camera.rotate(0.0, a, 0.0);
+                    scene.camera.rotate_quat(&yaw(a));
                     println!("Camera position: {:?}", scene.camera.position);
                 },
                 Event::KeyDown { keycode: Some(Keycode::Right), .. } => {
-                    scene.camera.rotate(0.0, -a, 0.0);
+                    scene.camera.rotate_quat(&yaw(-a));
                     println!("Camera position: {:?}", scene.camera.position);
                 },
                 _ => {}
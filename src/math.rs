@@ -48,9 +48,68 @@ impl Vec3 {
         ]
     }
 
+    /// Unnormalized cross product, as opposed to `cross` which gives both unit normals.
+    pub fn cross_product(&self, other: &Vec3) -> Vec3 {
+        Vec3 {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
     pub fn angle(&self, other: &Vec3) -> f32 {
         self.dot(other).acos()
     }
+
+    /// Mirror image of this direction about the plane of `normal`.
+    pub fn reflect(&self, normal: &Vec3) -> Vec3 {
+        *self - 2.0 * self.dot(normal) * *normal
+    }
+
+    /// Direction of this unit vector after crossing a surface according to Snell's law,
+    /// `eta` being the ratio of the refractive indices (incident over transmitted side)
+    /// and `normal` pointing to the incident side.
+    /// Gives `None` on total internal reflection.
+    pub fn refract(&self, normal: &Vec3, eta: f32) -> Option<Vec3> {
+        let cos_i = -self.dot(normal);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+
+        if sin2_t > 1.0 {
+            None
+        } else {
+            let cos_t = (1.0 - sin2_t).sqrt();
+            Some(eta * *self + (eta * cos_i - cos_t) * *normal)
+        }
+    }
+
+    pub fn min(&self, other: &Vec3) -> Vec3 {
+        Vec3 {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+
+    pub fn max(&self, other: &Vec3) -> Vec3 {
+        Vec3 {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z),
+        }
+    }
+}
+
+impl ops::Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &f32 {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index out of bounds: {}", index),
+        }
+    }
 }
 
 impl ops::Add<Vec3> for Vec3 {
@@ -133,6 +192,18 @@ impl ops::DivAssign<f32> for Vec3 {
     }
 }
 
+impl ops::Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Mat3 {
     pub coords: [[f32; 3]; 3],
@@ -370,6 +441,12 @@ impl Mat3 {
         )
     }
 
+    pub fn determinant(&self) -> f32 {
+          self.coords[0][0] * self.sub_factor(0, 0)
+        + self.coords[0][1] * self.sub_factor(0, 1)
+        + self.coords[0][2] * self.sub_factor(0, 2)
+    }
+
     pub fn invert(&self) -> Option<Mat3> {
 
         let co_matrix = Mat3 {
@@ -381,11 +458,7 @@ impl Mat3 {
         };
 
         let co_matrix_t = co_matrix.transpose();
-
-        let determinant =
-              self.coords[0][0] * co_matrix_t.coords[0][0]
-            + self.coords[0][1] * co_matrix_t.coords[1][0]
-            + self.coords[0][2] * co_matrix_t.coords[2][0];
+        let determinant = self.determinant();
 
         if determinant == 0.0 {
             None
@@ -447,6 +520,619 @@ impl Mat3 {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vec4 {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Vec4 {
+        Vec4 { x, y, z, w }
+    }
+
+    /// Homogeneous coordinates of a point, affected by translations.
+    pub fn point(p: &Vec3) -> Vec4 {
+        Vec4 { x: p.x, y: p.y, z: p.z, w: 1.0 }
+    }
+
+    /// Homogeneous coordinates of a direction, ignoring translations.
+    pub fn direction(d: &Vec3) -> Vec4 {
+        Vec4 { x: d.x, y: d.y, z: d.z, w: 0.0 }
+    }
+
+    pub fn xyz(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    /// Back to cartesian coordinates, dividing by `w`.
+    pub fn to_point(&self) -> Vec3 {
+        Vec3::new(self.x / self.w, self.y / self.w, self.z / self.w)
+    }
+
+    pub fn dot(&self, other: &Vec4) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+}
+
+impl ops::Index<usize> for Vec4 {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &f32 {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            3 => &self.w,
+            _ => panic!("Vec4 index out of bounds: {}", index),
+        }
+    }
+}
+
+impl ops::Add<Vec4> for Vec4 {
+    type Output = Vec4;
+
+    fn add(self, other: Vec4) -> Vec4 {
+        Vec4 {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+            w: self.w + other.w,
+        }
+    }
+}
+
+impl ops::Sub<Vec4> for Vec4 {
+    type Output = Vec4;
+
+    fn sub(self, other: Vec4) -> Vec4 {
+        Vec4 {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+            w: self.w - other.w,
+        }
+    }
+}
+
+impl ops::Mul<Vec4> for f32 {
+    type Output = Vec4;
+
+    fn mul(self, rhs: Vec4) -> Vec4 {
+        Vec4 {
+            x: self * rhs.x,
+            y: self * rhs.y,
+            z: self * rhs.z,
+            w: self * rhs.w,
+        }
+    }
+}
+
+impl ops::Div<f32> for Vec4 {
+    type Output = Vec4;
+
+    fn div(self, rhs: f32) -> Vec4 {
+        Vec4 {
+            x: self.x / rhs,
+            y: self.y / rhs,
+            z: self.z / rhs,
+            w: self.w / rhs,
+        }
+    }
+}
+
+impl ops::Neg for Vec4 {
+    type Output = Vec4;
+
+    fn neg(self) -> Vec4 {
+        Vec4 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+            w: -self.w,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub coords: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(coords: [[f32; 4]; 4]) -> Mat4 {
+        Mat4 { coords }
+    }
+
+    /// Affine matrix applying `linear` then moving by `translation`.
+    pub fn affine(linear: &Mat3, translation: &Vec3) -> Mat4 {
+        let m = &linear.coords;
+        Mat4 {
+            coords: [
+                [m[0][0], m[0][1], m[0][2], translation.x],
+                [m[1][0], m[1][1], m[1][2], translation.y],
+                [m[2][0], m[2][1], m[2][2], translation.z],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Upper left 3x3 block, the linear part of an affine matrix.
+    pub fn linear(&self) -> Mat3 {
+        let m = &self.coords;
+        Mat3 {
+            coords: [
+                [m[0][0], m[0][1], m[0][2]],
+                [m[1][0], m[1][1], m[1][2]],
+                [m[2][0], m[2][1], m[2][2]],
+            ],
+        }
+    }
+
+    pub fn equals(&self, other: &Mat4, epsilon: f32) -> bool {
+        for i in 0..4 {
+            for j in 0..4 {
+                if (self.coords[i][j] - other.coords[i][j]).abs() > epsilon {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut result = ZERO_MAT4;
+        for i in 0..4 {
+            for j in 0..4 {
+                result.coords[i][j] = self.coords[j][i];
+            }
+        }
+        result
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting.
+    /// The matrix is considered singular when a pivot is negligible compared to its coefficients.
+    pub fn invert(&self) -> Option<Mat4> {
+        let mut m = self.coords;
+        let mut inv = ID_MAT4.coords;
+
+        let largest = m.iter().flatten().fold(0.0_f32, |max, value| max.max(value.abs()));
+        let epsilon = 4.0 * f32::EPSILON * largest;
+
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))
+                .unwrap();
+
+            if m[pivot][col].abs() <= epsilon {
+                return None;
+            }
+
+            m.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let factor = 1.0 / m[col][col];
+            for j in 0..4 {
+                m[col][j] *= factor;
+                inv[col][j] *= factor;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor = m[row][col];
+                    for j in 0..4 {
+                        m[row][j] -= factor * m[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Mat4 { coords: inv })
+    }
+}
+
+impl ops::Add<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn add(self, other: Mat4) -> Mat4 {
+        let mut result = self;
+        for i in 0..4 {
+            for j in 0..4 {
+                result.coords[i][j] += other.coords[i][j];
+            }
+        }
+        result
+    }
+}
+
+impl ops::Sub<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn sub(self, other: Mat4) -> Mat4 {
+        let mut result = self;
+        for i in 0..4 {
+            for j in 0..4 {
+                result.coords[i][j] -= other.coords[i][j];
+            }
+        }
+        result
+    }
+}
+
+impl ops::Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Self::Output {
+        let mut result = ZERO_MAT4;
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    result.coords[i][j] += self.coords[i][k] * rhs.coords[k][j];
+                }
+            }
+        }
+        result
+    }
+}
+
+impl ops::Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+
+    fn mul(self, rhs: Vec4) -> Self::Output {
+        let row = |i: usize| Vec4::new(self.coords[i][0], self.coords[i][1], self.coords[i][2], self.coords[i][3]).dot(&rhs);
+        Vec4::new(row(0), row(1), row(2), row(3))
+    }
+}
+
+impl ops::Mul<Mat4> for f32 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Self::Output {
+        let mut result = rhs;
+        for row in result.coords.iter_mut() {
+            for value in row.iter_mut() {
+                *value *= self;
+            }
+        }
+        result
+    }
+}
+
+impl ops::Div<f32> for Mat4 {
+    type Output = Mat4;
+
+    fn div(self, rhs: f32) -> Self::Output {
+        (1.0 / rhs) * self
+    }
+}
+
+pub static ID_MAT4: Mat4 = Mat4 {
+    coords: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+};
+
+pub static ZERO_MAT4: Mat4 = Mat4 {
+    coords: [[0.0; 4]; 4],
+};
+
+/// Affine transform of the space, along with its inverse.
+///
+/// Points are affected by translations while directions aren't,
+/// normals go through the inverse transpose to stay orthogonal to the transformed surfaces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub matrix: Mat4,
+    pub inverse: Mat4,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform { matrix: ID_MAT4, inverse: ID_MAT4 }
+    }
+
+    /// `None` if the matrix can't be inverted.
+    pub fn from_matrix(matrix: Mat4) -> Option<Transform> {
+        Some(Transform { matrix, inverse: matrix.invert()? })
+    }
+
+    pub fn translation(d_pos: &Vec3) -> Transform {
+        Transform {
+            matrix: Mat4::affine(&ID_MAT3, d_pos),
+            inverse: Mat4::affine(&ID_MAT3, &-*d_pos),
+        }
+    }
+
+    /// Rotation around the origin, `rotation` must be orthonormal.
+    pub fn rotation(rotation: &Mat3) -> Transform {
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        Transform {
+            matrix: Mat4::affine(rotation, &origin),
+            inverse: Mat4::affine(&rotation.transpose(), &origin),
+        }
+    }
+
+    /// Same angles as `Mat3::rot_x_y_z`.
+    pub fn rotation_x_y_z(theta_x: f32, theta_y: f32, theta_z: f32) -> Transform {
+        Transform::rotation(&Mat3::rot_x_y_z(theta_x, theta_y, theta_z))
+    }
+
+    pub fn rotation_quat(rotation: &Quat) -> Transform {
+        Transform::rotation(&rotation.to_mat3())
+    }
+
+    /// Scale along each axis, none of the factors may be zero.
+    pub fn scale(factors: &Vec3) -> Transform {
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let diagonal = |x: f32, y: f32, z: f32| Mat3::new([[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, z]]);
+        Transform {
+            matrix: Mat4::affine(&diagonal(factors.x, factors.y, factors.z), &origin),
+            inverse: Mat4::affine(&diagonal(1.0 / factors.x, 1.0 / factors.y, 1.0 / factors.z), &origin),
+        }
+    }
+
+    pub fn uniform_scale(factor: f32) -> Transform {
+        Transform::scale(&Vec3::new(factor, factor, factor))
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    /// Applies `linear` then moves by `translation`, `None` if `linear` can't be inverted.
+    pub fn affine(linear: &Mat3, translation: &Vec3) -> Option<Transform> {
+        let inverse = linear.invert()?;
+        Some(Transform {
+            matrix: Mat4::affine(linear, translation),
+            inverse: Mat4::affine(&inverse, &-(inverse * *translation)),
+        })
+    }
+
+    /// Splits the transform into a translation, a rotation and a scale (possibly with shearing)
+    /// applied in the reverse order.
+    pub fn decompose(&self) -> (Vec3, Quat, Mat3) {
+        let c = &self.matrix.coords;
+        let translation = Vec3::new(c[0][3], c[1][3], c[2][3]);
+        let linear = self.matrix.linear();
+
+        // polar decomposition: averaging the matrix with its inverse transpose converges to the rotation
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let inverse_transpose = match rotation.invert() {
+                Some(inverse) => inverse.transpose(),
+                None => break,
+            };
+            let next = 0.5 * (rotation + inverse_transpose);
+            let converged = next.equals(&rotation, 1e-6);
+            rotation = next;
+            if converged {
+                break;
+            }
+        }
+
+        // a mirror converges to an orthogonal matrix with a determinant of -1, which isn't a rotation:
+        // the reflection is moved to the scale
+        if rotation.determinant() < 0.0 {
+            rotation = -1.0 * rotation;
+        }
+
+        let scale = rotation.transpose() * linear;
+        (translation, Quat::from_mat3(&rotation), scale)
+    }
+
+    /// Transform in between `self` (t = 0) and `other` (t = 1): translations and scales are
+    /// interpolated linearly while rotations turn at a constant speed.
+    pub fn interpolate(&self, other: &Transform, t: f32) -> Transform {
+        let (translation_a, rotation_a, scale_a) = self.decompose();
+        let (translation_b, rotation_b, scale_b) = other.decompose();
+
+        let translation = (1.0 - t) * translation_a + t * translation_b;
+        let rotation = rotation_a.slerp(&rotation_b, t);
+        let scale = (1.0 - t) * scale_a + t * scale_b;
+
+        Transform::from_parts(&translation, &rotation, &scale).unwrap_or(*self)
+    }
+
+    /// Inverse of `decompose`, `None` if the scale can't be inverted.
+    pub fn from_parts(translation: &Vec3, rotation: &Quat, scale: &Mat3) -> Option<Transform> {
+        Transform::affine(&(rotation.to_mat3() * *scale), translation)
+    }
+
+    /// Applies `self` then `other`, same as `other * self`.
+    pub fn then(&self, other: &Transform) -> Transform {
+        *other * *self
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        (self.matrix * Vec4::point(p)).to_point()
+    }
+
+    pub fn direction(&self, d: &Vec3) -> Vec3 {
+        (self.matrix * Vec4::direction(d)).xyz()
+    }
+
+    /// Transformed normal, not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.inverse.linear().transpose() * *n
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl ops::Mul<Transform> for Transform {
+    type Output = Transform;
+
+    /// Composition: `rhs` is applied first.
+    fn mul(self, rhs: Transform) -> Self::Output {
+        Transform {
+            matrix: self.matrix * rhs.matrix,
+            inverse: rhs.inverse * self.inverse,
+        }
+    }
+}
+
+/// Quaternion `w + xi + yj + zk`, unit ones representing rotations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quat {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Quat {
+        Quat { w, x, y, z }
+    }
+
+    pub fn identity() -> Quat {
+        Quat::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Counterclockwise rotation of `angle` radians around `axis`, which doesn't need to be normalized.
+    pub fn from_axis_angle(axis: &Vec3, angle: f32) -> Quat {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        Quat::new(cos, sin * axis.x, sin * axis.y, sin * axis.z)
+    }
+
+    /// Same rotation as `Mat3::rot_x_y_z`.
+    pub fn from_euler(theta_x: f32, theta_y: f32, theta_z: f32) -> Quat {
+        Quat::from_axis_angle(&Vec3::new(1.0, 0.0, 0.0), theta_x)
+            * Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), theta_y)
+            * Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), theta_z)
+    }
+
+    /// Rotation of the orthonormal matrix `m`.
+    pub fn from_mat3(m: &Mat3) -> Quat {
+        let c = &m.coords;
+        let trace = c[0][0] + c[1][1] + c[2][2];
+
+        // divide by the largest of the four components to stay accurate
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Quat::new(s / 4.0, (c[2][1] - c[1][2]) / s, (c[0][2] - c[2][0]) / s, (c[1][0] - c[0][1]) / s)
+        } else if c[0][0] > c[1][1] && c[0][0] > c[2][2] {
+            let s = 2.0 * (1.0 + c[0][0] - c[1][1] - c[2][2]).sqrt();
+            Quat::new((c[2][1] - c[1][2]) / s, s / 4.0, (c[0][1] + c[1][0]) / s, (c[0][2] + c[2][0]) / s)
+        } else if c[1][1] > c[2][2] {
+            let s = 2.0 * (1.0 + c[1][1] - c[0][0] - c[2][2]).sqrt();
+            Quat::new((c[0][2] - c[2][0]) / s, (c[0][1] + c[1][0]) / s, s / 4.0, (c[1][2] + c[2][1]) / s)
+        } else {
+            let s = 2.0 * (1.0 + c[2][2] - c[0][0] - c[1][1]).sqrt();
+            Quat::new((c[1][0] - c[0][1]) / s, (c[0][2] + c[2][0]) / s, (c[1][2] + c[2][1]) / s, s / 4.0)
+        };
+
+        q.normalize()
+    }
+
+    pub fn to_mat3(&self) -> Mat3 {
+        let Quat { w, x, y, z } = self.normalize();
+
+        Mat3 {
+            coords: [
+                [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+                [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+                [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+            ],
+        }
+    }
+
+    pub fn dot(&self, other: &Quat) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn norm(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Quat {
+        (1.0 / self.norm()) * *self
+    }
+
+    /// Inverse rotation, for unit quaternions.
+    pub fn conjugate(&self) -> Quat {
+        Quat::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    /// Applies the rotation to `v`, the quaternion must be normalized.
+    pub fn rotate(&self, v: &Vec3) -> Vec3 {
+        let u = Vec3::new(self.x, self.y, self.z);
+        let t = 2.0 * u.cross_product(v);
+        *v + self.w * t + u.cross_product(&t)
+    }
+
+    /// Normalized linear interpolation: cheap, but the rotation speed isn't constant along the way.
+    pub fn nlerp(&self, other: &Quat, t: f32) -> Quat {
+        // q and -q are the same rotation, go the short way
+        let other = if self.dot(other) < 0.0 { -*other } else { *other };
+        ((1.0 - t) * *self + t * other).normalize()
+    }
+
+    /// Spherical linear interpolation, rotating at a constant speed from `self` (t = 0) to `other` (t = 1).
+    pub fn slerp(&self, other: &Quat, t: f32) -> Quat {
+        let mut cos = self.dot(other);
+        let other = if cos < 0.0 {
+            cos = -cos;
+            -*other
+        } else {
+            *other
+        };
+
+        // nearly identical rotations: the sine below would vanish
+        if cos > 0.9995 {
+            return self.nlerp(&other, t);
+        }
+
+        let angle = cos.acos();
+        let sin = angle.sin();
+        (((1.0 - t) * angle).sin() / sin) * *self + ((t * angle).sin() / sin) * other
+    }
+}
+
+impl ops::Add<Quat> for Quat {
+    type Output = Quat;
+
+    fn add(self, other: Quat) -> Quat {
+        Quat::new(self.w + other.w, self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl ops::Mul<Quat> for Quat {
+    type Output = Quat;
+
+    /// Hamilton product, the rotation `rhs` followed by `self`.
+    fn mul(self, rhs: Quat) -> Quat {
+        Quat {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+}
+
+impl ops::Mul<Quat> for f32 {
+    type Output = Quat;
+
+    fn mul(self, rhs: Quat) -> Quat {
+        Quat::new(self * rhs.w, self * rhs.x, self * rhs.y, self * rhs.z)
+    }
+}
+
+impl ops::Neg for Quat {
+    type Output = Quat;
+
+    fn neg(self) -> Quat {
+        Quat::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
 pub struct Line {
     pub origin: Vec3,
     pub direction: Vec3,
@@ -538,6 +1224,13 @@ mod tests {
         assert_eq!(v, expected, "vector division failed");
     }
 
+    #[test]
+    fn test_neg_vector() {
+        let v = Vec3::new(1.0, -2.0, 3.0);
+        let expected = Vec3::new(-1.0, 2.0, -3.0);
+        assert_eq!(-v, expected, "vector negation failed");
+    }
+
     #[test]
     fn test_add_matrices() {
         let m = Mat3::new([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
@@ -643,6 +1336,52 @@ mod tests {
         assert_eq!(actual, expected, "vector dot product failed");
     }
 
+    #[test]
+    fn test_vector_cross_product() {
+        let v1 = Vec3::new(2.0, 0.0, 0.0);
+        let v2 = Vec3::new(0.0, 3.0, 0.0);
+        assert_eq!(v1.cross_product(&v2), Vec3::new(0.0, 0.0, 6.0), "vector cross product failed");
+        assert_eq!(v2.cross_product(&v1), Vec3::new(0.0, 0.0, -6.0), "vector cross product failed");
+    }
+
+    #[test]
+    fn test_vector_reflect() {
+        let v = Vec3::new(1.0, -1.0, 0.0);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(v.reflect(&normal), Vec3::new(1.0, 1.0, 0.0), "vector reflection failed");
+    }
+
+    #[test]
+    fn test_vector_refract() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+
+        let straight = Vec3::new(0.0, -1.0, 0.0).refract(&normal, 1.0 / 1.5).unwrap();
+        assert!((straight - Vec3::new(0.0, -1.0, 0.0)).norm() < 1e-6, "refraction failed: {:?}", straight);
+
+        // snell: sin(theta_t) = eta * sin(theta_i)
+        let incident = Vec3::new(0.5f32.sqrt(), -(0.5f32.sqrt()), 0.0);
+        let refracted = incident.refract(&normal, 1.0 / 1.5).unwrap();
+        assert!((refracted.norm() - 1.0).abs() < 1e-6, "refracted vector should stay unit");
+        assert!((refracted.x - 0.5f32.sqrt() / 1.5).abs() < 1e-6, "refraction failed: {:?}", refracted);
+
+        // from glass to air past the critical angle
+        assert!(incident.refract(&normal, 1.5).is_none(), "total internal reflection expected");
+    }
+
+    #[test]
+    fn test_vector_index() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!([v[0], v[1], v[2]], [1.0, 2.0, 3.0], "vector indexing failed");
+    }
+
+    #[test]
+    fn test_vector_min_max() {
+        let v1 = Vec3::new(1.0, 5.0, 3.0);
+        let v2 = Vec3::new(4.0, 2.0, 3.0);
+        assert_eq!(v1.min(&v2), Vec3::new(1.0, 2.0, 3.0), "vector min failed");
+        assert_eq!(v1.max(&v2), Vec3::new(4.0, 5.0, 3.0), "vector max failed");
+    }
+
     struct RotationTest {
         src: Vec3,
         target: Vec3,
@@ -688,4 +1427,209 @@ mod tests {
             test_rotation(t);
         }
     }
+
+    #[test]
+    fn test_vec4_operations() {
+        let u = Vec4::new(1.0, 2.0, 3.0, 4.0);
+        let v = Vec4::new(4.0, 3.0, 2.0, 1.0);
+        assert_eq!(u + v, Vec4::new(5.0, 5.0, 5.0, 5.0), "vector addition failed");
+        assert_eq!(u - v, Vec4::new(-3.0, -1.0, 1.0, 3.0), "vector subtraction failed");
+        assert_eq!(2.0 * u, Vec4::new(2.0, 4.0, 6.0, 8.0), "scalar multiplication failed");
+        assert_eq!(u / 2.0, Vec4::new(0.5, 1.0, 1.5, 2.0), "vector division failed");
+        assert_eq!(-u, Vec4::new(-1.0, -2.0, -3.0, -4.0), "vector negation failed");
+        assert_eq!(u.dot(&v), 20.0, "dot product failed");
+        assert_eq!(u[3], 4.0, "vector index failed");
+        assert_eq!(Vec4::new(2.0, 4.0, 6.0, 2.0).to_point(), Vec3::new(1.0, 2.0, 3.0), "homogeneous division failed");
+    }
+
+    #[test]
+    fn test_mat4_operations() {
+        let m = Mat4::new([[1.0, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0], [9.0, 10.0, 11.0, 12.0], [13.0, 14.0, 15.0, 16.0]]);
+        assert_eq!(m + m, 2.0 * m, "matrix addition failed");
+        assert_eq!(m - m, ZERO_MAT4, "matrix subtraction failed");
+        assert_eq!((2.0 * m) / 2.0, m, "matrix division failed");
+        assert_eq!(m * ID_MAT4, m, "matrix multiplication failed");
+        assert_eq!(m.transpose().coords[0], [1.0, 5.0, 9.0, 13.0], "matrix transpose failed");
+
+        let actual = m * Vec4::new(1.0, 0.0, -1.0, 1.0);
+        assert_eq!(actual, Vec4::new(2.0, 6.0, 10.0, 14.0), "matrix-vector multiplication failed");
+
+        let singular = Mat4::new([[1.0, 2.0, 3.0, 4.0], [2.0, 4.0, 6.0, 8.0], [9.0, 10.0, 11.0, 12.0], [0.0, 1.0, 0.0, 1.0]]);
+        assert!(singular.invert().is_none(), "matrix inverse failed");
+    }
+
+    #[test]
+    fn test_many_mat4_inverses() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let mut m = ZERO_MAT4;
+            for row in m.coords.iter_mut() {
+                for value in row.iter_mut() {
+                    *value = rng.gen_range(-1.0..1.0);
+                }
+            }
+            // diagonally dominant, hence invertible and well conditioned
+            for i in 0..4 {
+                m.coords[i][i] += 4.0;
+            }
+
+            let inv = m.invert().unwrap();
+            assert!((m * inv).equals(&ID_MAT4, 0.0001), "matrix inverse failed");
+            assert!((inv * m).equals(&ID_MAT4, 0.0001), "matrix inverse failed");
+        }
+    }
+
+    #[test]
+    fn test_transform_points_and_directions() {
+        let translation = Transform::translation(&Vec3::new(1.0, 2.0, 3.0));
+        let p = Vec3::new(1.0, 1.0, 1.0);
+        assert_eq!(translation.point(&p), Vec3::new(2.0, 3.0, 4.0), "point translation failed");
+        assert_eq!(translation.direction(&p), p, "directions shouldn't be translated");
+
+        let scale = Transform::scale(&Vec3::new(2.0, 3.0, 4.0));
+        assert_eq!(scale.point(&p), Vec3::new(2.0, 3.0, 4.0), "point scaling failed");
+        assert_eq!(scale.direction(&p), Vec3::new(2.0, 3.0, 4.0), "direction scaling failed");
+
+        let rotation = Transform::rotation(&Mat3::rot_z(std::f32::consts::PI / 2.0));
+        let actual = rotation.point(&Vec3::new(1.0, 0.0, 0.0));
+        assert!((actual - Vec3::new(0.0, 1.0, 0.0)).norm() < 0.0001, "point rotation failed");
+    }
+
+    #[test]
+    fn test_transform_composition_and_inverse() {
+        let transform = Transform::scale(&Vec3::new(2.0, 1.0, 1.0))
+            .then(&Transform::rotation_x_y_z(0.3, -1.2, 0.7))
+            .then(&Transform::translation(&Vec3::new(5.0, -2.0, 1.0)));
+
+        let p = Vec3::new(1.0, 2.0, 3.0);
+        let expected = Mat3::rot_x_y_z(0.3, -1.2, 0.7) * Vec3::new(2.0, 2.0, 3.0) + Vec3::new(5.0, -2.0, 1.0);
+        assert!((transform.point(&p) - expected).norm() < 0.0001, "composition failed");
+
+        let back = transform.inverse().point(&transform.point(&p));
+        assert!((back - p).norm() < 0.0001, "inverse transform failed");
+        assert!((transform.matrix * transform.inverse).equals(&ID_MAT4, 0.0001), "inverse matrix failed");
+
+        let from_matrix = Transform::from_matrix(transform.matrix).unwrap();
+        assert!(from_matrix.inverse.equals(&transform.inverse, 0.0001), "matrix inverse failed");
+    }
+
+    #[test]
+    fn test_transform_normals() {
+        // plane x = y, squashed along x: its normal must stay orthogonal to it
+        let transform = Transform::scale(&Vec3::new(4.0, 1.0, 1.0));
+        let tangent = transform.direction(&Vec3::new(1.0, 1.0, 0.0));
+        let normal = transform.normal(&Vec3::new(1.0, -1.0, 0.0));
+
+        assert!(tangent.dot(&normal).abs() < 0.0001, "normal transform failed");
+        assert!(transform.direction(&Vec3::new(1.0, -1.0, 0.0)).dot(&tangent).abs() > 1.0, "scaled normal shouldn't be orthogonal");
+    }
+
+    #[test]
+    fn test_quat_axis_angle() {
+        let q = Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 2.0), std::f32::consts::PI / 2.0);
+        let actual = q.rotate(&Vec3::new(1.0, 0.0, 0.0));
+        assert!((actual - Vec3::new(0.0, 1.0, 0.0)).norm() < 0.0001, "quaternion rotation failed");
+        assert!((q.norm() - 1.0).abs() < 0.0001, "rotation quaternion should be normalized");
+        assert_eq!(Quat::new(2.0, 0.0, 0.0, 0.0).normalize(), Quat::identity(), "normalization failed");
+    }
+
+    #[test]
+    fn test_quat_matches_euler_matrices() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let (x, y, z) = (rng.gen_range(-3.0..3.0), rng.gen_range(-3.0..3.0), rng.gen_range(-3.0..3.0));
+            let q = Quat::from_euler(x, y, z);
+            let m = Mat3::rot_x_y_z(x, y, z);
+            let v = Vec3::new(rng.gen(), rng.gen(), rng.gen());
+
+            assert!(q.to_mat3().equals(&m, 0.0001), "quaternion to matrix failed");
+            assert!((q.rotate(&v) - m * v).norm() < 0.0001, "quaternion rotation failed");
+
+            // q and -q are the same rotation
+            let back = Quat::from_mat3(&m);
+            assert!((back.dot(&q).abs() - 1.0).abs() < 0.0001, "matrix to quaternion failed: {:?} {:?}", q, back);
+        }
+    }
+
+    #[test]
+    fn test_quat_multiplication() {
+        let a = Quat::from_axis_angle(&Vec3::new(1.0, 2.0, 3.0), 0.7);
+        let b = Quat::from_axis_angle(&Vec3::new(-1.0, 0.0, 1.0), 1.9);
+        let v = Vec3::new(0.5, -1.0, 2.0);
+
+        let actual = (a * b).rotate(&v);
+        let expected = a.rotate(&b.rotate(&v));
+        assert!((actual - expected).norm() < 0.0001, "quaternion product failed");
+        assert!((a * a.conjugate()).dot(&Quat::identity()) > 0.9999, "quaternion conjugate failed");
+    }
+
+    #[test]
+    fn test_quat_interpolation() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let start = Quat::identity();
+        let end = Quat::from_axis_angle(&axis, 2.0);
+
+        for t in [0.0, 0.25, 0.5, 1.0] {
+            let expected = Quat::from_axis_angle(&axis, 2.0 * t);
+            assert!((start.slerp(&end, t).dot(&expected) - 1.0).abs() < 0.0001, "slerp failed at {}", t);
+        }
+
+        // nlerp follows the same path, at a different speed
+        let halfway = start.nlerp(&end, 0.5);
+        assert!((halfway.dot(&Quat::from_axis_angle(&axis, 1.0)) - 1.0).abs() < 0.0001, "nlerp failed");
+        assert!((start.nlerp(&end, 0.25).norm() - 1.0).abs() < 0.0001, "nlerp should be normalized");
+
+        // -end is the same rotation, interpolation goes the short way
+        let actual = start.slerp(&-end, 0.5);
+        assert!((actual.dot(&Quat::from_axis_angle(&axis, 1.0)).abs() - 1.0).abs() < 0.0001, "slerp failed");
+    }
+
+    #[test]
+    fn test_transform_decompose() {
+        let transform = Transform::scale(&Vec3::new(2.0, 3.0, 0.5))
+            .then(&Transform::rotation_x_y_z(0.4, 1.1, -0.6))
+            .then(&Transform::translation(&Vec3::new(1.0, -2.0, 3.0)));
+
+        let (translation, rotation, scale) = transform.decompose();
+        assert!((translation - Vec3::new(1.0, -2.0, 3.0)).norm() < 0.0001, "translation failed");
+        assert!(rotation.to_mat3().equals(&Mat3::rot_x_y_z(0.4, 1.1, -0.6), 0.0001), "rotation failed");
+        let expected = Mat3::new([[2.0, 0.0, 0.0], [0.0, 3.0, 0.0], [0.0, 0.0, 0.5]]);
+        assert!(scale.equals(&expected, 0.0001), "scale failed: {:?}", scale);
+    }
+
+    #[test]
+    fn test_mirrored_transform_decompose() {
+        let transform = Transform::scale(&Vec3::new(-1.0, 1.0, 1.0))
+            .then(&Transform::rotation_x_y_z(0.0, 0.0, 0.7))
+            .then(&Transform::translation(&Vec3::new(3.0, 0.0, 0.0)));
+
+        let (translation, rotation, scale) = transform.decompose();
+        assert!(scale.determinant() < 0.0, "reflection lost: {:?}", scale);
+        let rebuilt = Transform::from_parts(&translation, &rotation, &scale).unwrap();
+        assert!(rebuilt.matrix.equals(&transform.matrix, 0.0001), "mirrored round trip failed");
+
+        let p = Vec3::new(1.0, 0.0, 0.0);
+        let halfway = transform.interpolate(&transform, 0.5);
+        assert!((halfway.point(&p) - transform.point(&p)).norm() < 0.0001, "mirrored interpolation failed");
+    }
+
+    #[test]
+    fn test_transform_interpolate() {
+        let start = Transform::identity();
+        let end = Transform::uniform_scale(3.0)
+            .then(&Transform::rotation_x_y_z(0.0, 0.0, std::f32::consts::PI / 2.0))
+            .then(&Transform::translation(&Vec3::new(10.0, 0.0, 0.0)));
+
+        let p = Vec3::new(1.0, 0.0, 0.0);
+        assert!((start.interpolate(&end, 0.0).point(&p) - p).norm() < 0.0001, "interpolation failed at 0");
+        assert!((start.interpolate(&end, 1.0).point(&p) - end.point(&p)).norm() < 0.0001, "interpolation failed at 1");
+
+        // halfway: scaled by 2, an eighth of a turn, moved by 5
+        let halfway = start.interpolate(&end, 0.5);
+        let expected = Vec3::new(5.0 + 2.0 * 0.5_f32.sqrt(), 2.0 * 0.5_f32.sqrt(), 0.0);
+        assert!((halfway.point(&p) - expected).norm() < 0.0001, "interpolation failed: {:?}", halfway.point(&p));
+        assert!((halfway.matrix * halfway.inverse).equals(&ID_MAT4, 0.0001), "interpolated inverse failed");
+    }
 }
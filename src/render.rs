@@ -1,215 +1,464 @@
-use sdl2::pixels::Color;
-use sdl2::rect::Point as SDLPoint;
-use sdl2::video::Window;
-use sdl2::render::Canvas;
-
 pub mod objects;
-
+pub mod camera;
+pub mod light;
+pub mod environment;
+pub mod bvh;
+pub mod mesh;
+pub mod instance;
+pub mod csg;
+pub mod obj;
+pub mod material;
+pub mod texture;
+pub mod framebuffer;
+pub mod image;
+pub mod sampling;
+pub mod path_tracer;
+pub mod accumulator;
+pub mod filter;
+pub mod tonemap;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+
+use rand::{Rng, RngCore};
 use rayon::prelude::*;
 
 use objects::{Scene, Ray};
 
-use self::objects::Intersection;
+use self::objects::{Intersection, Light};
+use self::material::{Material, Specular};
+use self::sampling::SamplePattern;
+
+pub use accumulator::Accumulator;
+pub use filter::Filter;
+pub use framebuffer::{Framebuffer, RenderTarget};
+pub use tonemap::ToneMapping;
+#[cfg(feature = "sdl")]
+pub use sdl::Display;
+
+/// Offset applied to the origin of secondary rays so that they don't hit the surface they start from.
+const SECONDARY_RAY_EPSILON: f32 = 1e-3;
+
+/// Algorithm used to compute the colour seen along each ray.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    /// Direct lighting from the lights and the environment, with recursive rays for mirrors and glass only.
+    Whitted,
+    /// Monte Carlo path tracing, accounting for indirect lighting.
+    PathTracer,
+}
 
-impl objects::Color {
-    pub fn as_sdl(colors: &Vec<objects::Color>) -> Color {
-        let mut r = 0.0;
-        let mut g = 0.0;
-        let mut b = 0.0;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    pub integrator: Integrator,
+    /// Number of rays traced through each pixel.
+    pub samples_per_pixel: u32,
+    /// Where the rays go through the pixels.
+    pub pattern: SamplePattern,
+    /// How the colours of the rays around each pixel are combined.
+    pub filter: Filter,
+    /// Maximum number of bounces followed by reflected and refracted rays.
+    pub max_depth: u32,
+    /// Shadow rays traced towards area lights at each shaded point, for smoother soft shadows.
+    pub light_samples: u32,
+}
 
-        for color in colors {
-            r += color.rgb.x;
-            g += color.rgb.y;
-            b += color.rgb.z;
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            integrator: Integrator::Whitted,
+            samples_per_pixel: 1,
+            pattern: SamplePattern::Grid,
+            filter: Filter::Box,
+            max_depth: 5,
+            light_samples: 1,
         }
-
-        let len = colors.len() as f32;
-
-        Color::RGB((r * 255.0 / len) as u8, (g * 255.0 / len) as u8, (b * 255.0 / len) as u8)
     }
 }
 
-pub struct Display {
-    pub canvas: Canvas<Window>,
-    pub width: u32,
-    pub height: u32,
-}
+/// Light reflected back along `ray` by the surface at `hit` coming directly from `light`,
+/// `None` when the light is hidden. Area lights are sampled `settings.light_samples` times.
+fn light_contribution(
+    scene: &Scene,
+    settings: &RenderSettings,
+    light: &Light,
+    material: &dyn Material,
+    hit: &Intersection,
+    ray: &Ray,
+    rng: &mut dyn RngCore,
+) -> Option<objects::Color> {
+    let count = if light.is_delta() { 1 } else { settings.light_samples.max(1) };
+    let view_dir = -ray.direction;
+
+    let mut total = objects::Color::black();
+    let mut lit = false;
+
+    for (u1, u2) in SamplePattern::Stratified.generate(count, rng) {
+        let sample = match light.sample(&hit.point, u1, u2) {
+            Some(sample) => sample,
+            None => continue,
+        };
 
-#[derive(Debug)]
-pub struct Point {
-    point: SDLPoint,
-    color: Color,
-}
+        let cos = hit.normal.dot(&sample.direction);
+        if cos <= 0.0 {
+            continue;
+        }
 
-impl Point {
-    pub fn new(x: i32, y: i32, color: Color) -> Point {
-        Point {
-            point: SDLPoint::new(x, y),
-            color,
+        let shadow_ray = Ray::new(hit.point + SECONDARY_RAY_EPSILON * hit.normal, sample.direction).at_time(ray.time);
+        if scene.occluded(&shadow_ray, 0.0, sample.distance) {
+            continue;
         }
+
+        total += material.eval(hit, &view_dir, &sample.direction) * sample.radiance * cos;
+        lit = true;
     }
-}
 
-impl Display {
-    pub fn new(canvas: Canvas<Window>, width: u32, height: u32) -> Display {
-        Display { canvas, width, height }
+    if lit {
+        Some(total * (1.0 / count as f32))
+    } else {
+        None
     }
 }
 
-fn compute_color(ray: &Ray, intersection: &Intersection, scene: &Scene) -> Color {
-    let a = ray.direction.dot(&intersection.normal);
+/// Light reflected back along `ray` by the surface at `hit` coming directly from the environment,
+/// `None` when it is black or hidden. It is sampled `settings.light_samples` times, the samples being
+/// weighted by multiple importance sampling when `mis` as the material samples the environment too.
+fn environment_contribution(
+    scene: &Scene,
+    settings: &RenderSettings,
+    material: &dyn Material,
+    hit: &Intersection,
+    ray: &Ray,
+    rng: &mut dyn RngCore,
+    mis: bool,
+) -> Option<objects::Color> {
+    if scene.environment.is_black() {
+        return None;
+    }
 
-    let c = (2.0 + a) / 2.0;
-    let epsilon = 1.0;
+    let count = settings.light_samples.max(1);
+    let view_dir = -ray.direction;
 
-    let mut colors: Vec<objects::Color> = scene.lights.iter().filter_map(|light| {
-        let direction = light.origin() - intersection.point;
+    let mut total = objects::Color::black();
+    let mut lit = false;
 
-        let shadowed = scene.shapes.iter().any(|shape| {
-            match shape.intersect(&Ray::new(intersection.point, direction)) {
-                Some(intersection) => intersection.dist > epsilon,
-                None => false,
-            }
-        });
+    for (u1, u2) in SamplePattern::Stratified.generate(count, rng) {
+        let (direction, pdf) = match scene.environment.sample(&hit.normal, u1, u2) {
+            Some(sample) => sample,
+            None => continue,
+        };
 
-        if shadowed {
-            return None;
+        let cos = hit.normal.dot(&direction);
+        if cos <= 0.0 || pdf <= 0.0 {
+            continue;
         }
 
-        // light distance
-        let d = direction.norm();
+        let shadow_ray = Ray::new(hit.point + SECONDARY_RAY_EPSILON * hit.normal, direction).at_time(ray.time);
+        if scene.occluded(&shadow_ray, 0.0, f32::INFINITY) {
+            continue;
+        }
 
-        Some(light.color.dim(d * d / 10000.0))
-    }).collect();
+        let weight = if mis {
+            sampling::power_heuristic(count as f32 * pdf, material.pdf(hit, &view_dir, &direction))
+        } else {
+            1.0
+        };
 
-    colors.push(objects::Color::new(c, c, c));
+        total += material.eval(hit, &view_dir, &direction) * scene.environment.radiance(&direction) * (cos * weight / pdf);
+        lit = true;
+    }
 
-    return objects::Color::as_sdl(&colors);
+    if lit {
+        Some(total * (1.0 / count as f32))
+    } else {
+        None
+    }
 }
 
-fn slice(distance: u32, parts: u32) -> Vec<u32> {
-    let length = (distance as f32 / parts as f32).floor() as u32;
-    let delta = distance - length * parts;
-    (0..parts).map(|i| length + if i < delta { 1 } else { 0 }).collect()
+/// What a camera ray leaving the scene sees: the environment when it serves as backdrop,
+/// `None` otherwise for the pixel to stay transparent.
+fn backdrop(ray: &Ray, scene: &Scene) -> Option<objects::Color> {
+    if scene.backdrop {
+        Some(scene.environment.radiance(&ray.direction))
+    } else {
+        None
+    }
 }
 
-fn ranges(distance: u32, parts: u32) -> Vec<(u32, u32)> {
-    let slices = slice(distance, parts);
-    let mut ranges: Vec<(u32, u32)> = Vec::new();
-    let mut start = 0;
-    for slice in slices {
-        ranges.push((start, start + slice));
-        start += slice;
+/// `intersection` with the shading normal of `material`, turned to the side of the surface
+/// `ray` comes from, and whether that is the outside (the side of the geometric normal).
+fn facing_hit(ray: &Ray, material: &dyn Material, intersection: &Intersection) -> (Intersection, bool) {
+    let outside = ray.direction.dot(&intersection.normal) < 0.0;
+    let normal = material.shading_normal(intersection);
+    let normal = if outside { normal } else { -normal };
+
+    (Intersection { normal, ..*intersection }, outside)
+}
+
+/// Light reflected or emitted by a diffuse surface towards the ray, `hit` facing the ray.
+fn compute_color(
+    ray: &Ray,
+    material: &dyn Material,
+    hit: &Intersection,
+    scene: &Scene,
+    settings: &RenderSettings,
+) -> objects::Color {
+    let mut rng = rand::thread_rng();
+    let mut color = material.emitted(hit);
+
+    if let Some(contribution) = environment_contribution(scene, settings, material, hit, ray, &mut rng, false) {
+        color += contribution;
+    }
+
+    for light in &scene.lights {
+        if let Some(contribution) = light_contribution(scene, settings, light, material, hit, ray, &mut rng) {
+            color += contribution;
+        }
     }
-    ranges
+
+    color
 }
 
-type Pair = (u32, u32);
-type RectCoords = (Pair, Pair);
-type Points1D = Vec<Point>;
-type Points2D = Vec<Points1D>;
-type Points3D = Vec<Points2D>;
+/// Follows the reflected and refracted rays off a perfectly specular surface, `hit` facing the ray.
+fn compute_specular(
+    ray: &Ray,
+    hit: &Intersection,
+    outside: bool,
+    specular: Specular,
+    scene: &Scene,
+    settings: &RenderSettings,
+    depth: u32,
+) -> objects::Color {
+    let normal = hit.normal;
+
+    let reflected = Ray::new(
+        hit.point + SECONDARY_RAY_EPSILON * normal,
+        ray.direction.reflect(&normal),
+    ).at_time(ray.time);
+
+    let trace_secondary = |ray: &Ray| {
+        trace(ray, scene, settings, depth + 1).unwrap_or(objects::Color::black())
+    };
 
-fn compute(scene: &Scene, screen: &Display) -> Points3D {
-    let bottom_left = scene.camera.screen.center - scene.camera.screen.width / 2.0 - scene.camera.screen.height / 2.0;
+    match specular {
+        Specular::Mirror(tint) => tint * trace_secondary(&reflected),
+        Specular::Dielectric { ior, tint } => {
+            let eta = if outside { 1.0 / ior } else { ior };
+            let reflectance = material::schlick(-ray.direction.dot(&normal), eta);
+            let reflection = trace_secondary(&reflected) * reflectance;
+
+            match ray.direction.refract(&normal, eta) {
+                Some(direction) if reflectance < 1.0 => {
+                    let refracted = Ray::new(hit.point - SECONDARY_RAY_EPSILON * normal, direction)
+                        .at_time(ray.time);
+                    reflection + tint * trace_secondary(&refracted) * (1.0 - reflectance)
+                },
+                // total internal reflection
+                _ => reflection,
+            }
+        },
+    }
+}
 
-    let x_slices = ranges(screen.width, 4);
-    let y_slices = ranges(screen.height, 4);
+/// Colour seen along `ray`, `None` when a camera ray doesn't hit anything and there is no backdrop.
+fn trace(ray: &Ray, scene: &Scene, settings: &RenderSettings, depth: u32) -> Option<objects::Color> {
+    let (index, intersection) = match scene.intersect(ray) {
+        Some(hit) => hit,
+        None if depth == 0 => return backdrop(ray, scene),
+        None => return Some(scene.environment.radiance(&ray.direction)),
+    };
+    let material = scene.material(index);
+    let (hit, outside) = facing_hit(ray, material, &intersection);
 
-    let screen_width = screen.width as f32;
-    let screen_height = screen.height as f32;
-    let scene_width = scene.camera.screen.width;
-    let scene_height = scene.camera.screen.height;
-    let camera_pos = scene.camera.position;
+    let color = match material.specular() {
+        None => compute_color(ray, material, &hit, scene, settings),
+        Some(_) if depth >= settings.max_depth => objects::Color::black(),
+        Some(specular) => compute_specular(ray, &hit, outside, specular, scene, settings, depth),
+    };
 
-    let t_start = std::time::Instant::now();
-    let screen_parts: Vec<RectCoords> = x_slices.iter().flat_map(|x| {
-        y_slices.iter().map(move |y| {
-            (*x, *y)
-        })
-    }).collect();
-    println!("Splitting screen: {:?}ms", t_start.elapsed().as_millis());
+    Some(color)
+}
 
-    let compute_x_y_pixel = |x: u32, y: u32| -> Option<Point> {
-        let screen_pos = bottom_left + (x as f32 / screen_width) * scene_width + (y as f32 / screen_height as f32) * scene_height;
+/// Traces `settings.samples_per_pixel` rays through each pixel and writes them into `framebuffer`,
+/// weighted by the reconstruction filter. The alpha channel holds the weight of the rays that hit something.
+pub fn compute(scene: &Scene, settings: &RenderSettings, framebuffer: &mut Framebuffer) {
+    compute_samples(scene, settings, settings.pattern, framebuffer);
+}
 
-        let ray = Ray::new(
-            screen_pos,
-            screen_pos - camera_pos,
-        );
+/// Rows of pixels traced together by a thread, each band splats its samples into its own buffer.
+const BAND_ROWS: u32 = 16;
 
-        let mut intersections: Vec<Intersection> = Vec::new();
+/// Samples around a pixel, summed with the weights of the reconstruction filter.
+#[derive(Clone, Copy)]
+struct Splat {
+    sum: objects::Color,
+    hit_weight: f32,
+    total_weight: f32,
+}
 
-        for shape in &scene.shapes {
-            let maybe_inter = shape.intersect(&ray);
+impl Splat {
+    fn new() -> Splat {
+        Splat { sum: objects::Color::black(), hit_weight: 0.0, total_weight: 0.0 }
+    }
 
-            if let Some(intersection) = maybe_inter {
-                intersections.push(
-                    intersection
-                );
-            }
+    fn add(&mut self, color: Option<objects::Color>, weight: f32) {
+        self.total_weight += weight;
+        if let Some(color) = color {
+            self.sum += color * weight;
+            self.hit_weight += weight;
         }
+    }
 
-        match Intersection::nearest(&mut intersections) {
-            Some(intersection) => {
-                Some(Point::new(
-                    x as i32,
-                    (screen_height - y as f32) as i32,
-                    compute_color(&ray, &intersection, &scene)
-                ))
-            },
-            None => None
+    fn merge(&mut self, other: &Splat) {
+        self.sum += other.sum;
+        self.hit_weight += other.hit_weight;
+        self.total_weight += other.total_weight;
+    }
+
+    fn pixel(&self) -> [f32; 4] {
+        // negative lobes can push the values out of range
+        if self.hit_weight <= 0.0 || self.total_weight <= 0.0 {
+            return [0.0; 4];
         }
-    };
 
-    let screen_part_processor = |(bl, tr): &RectCoords| -> Points2D {
-        (bl.0..bl.1).map(|x| {
-            (tr.0..tr.1).map(|y| {
-                compute_x_y_pixel(x, y)
-            }).filter(|maybe_point| {
-                maybe_point.is_some()
-            }).map(|maybe_point| {
-                maybe_point.unwrap()
-            }).collect::<Points1D>()
-        }).collect::<Points2D>()
+        let color = self.sum * (1.0 / self.hit_weight);
+        [
+            color.rgb.x.max(0.0),
+            color.rgb.y.max(0.0),
+            color.rgb.z.max(0.0),
+            (self.hit_weight / self.total_weight).min(1.0),
+        ]
+    }
+}
+
+/// Same as `compute`, with the samples spread over the pixels according to `pattern`.
+fn compute_samples(scene: &Scene, settings: &RenderSettings, pattern: SamplePattern, framebuffer: &mut Framebuffer) {
+    let width = framebuffer.width;
+    let height = framebuffer.height;
+
+    let screen_width = framebuffer.width as f32;
+    let screen_height = framebuffer.height as f32;
+
+    let samples_per_pixel = settings.samples_per_pixel.max(1);
+
+    let compute_x_y_sample = |x: f32, y: f32, rng: &mut rand::rngs::ThreadRng| -> Option<objects::Color> {
+        let ray = scene.camera.lens_ray(x / screen_width, y / screen_height, rng.gen(), rng.gen())?
+            .at_time(scene.camera.shutter_time(rng.gen()));
+
+        match settings.integrator {
+            Integrator::Whitted => trace(&ray, scene, settings, 0),
+            Integrator::PathTracer => path_tracer::trace(&ray, scene, settings, rng),
+        }
     };
 
-    let pixels: Points3D = screen_parts.par_iter().map(
-        screen_part_processor
-    ).collect();
+    // each sample is added right away to the pixels in reach of the filter, so that memory
+    // doesn't grow with the number of samples
+    let filter = settings.filter;
+    let reach = filter.radius().ceil() as u32;
+
+    // rows are stored top to bottom while y goes up on the screen,
+    // pixel (x, y) is centered on the point (x, y) of the screen
+    let bands: Vec<u32> = (0..height).step_by(BAND_ROWS as usize).collect();
+    let bands: Vec<(usize, Vec<Splat>)> = bands.into_par_iter().map(|start| {
+        let end = (start + BAND_ROWS).min(height);
+        let first_row = start.saturating_sub(reach);
+        let last_row = (end + reach).min(height);
+
+        let mut splats = vec![Splat::new(); ((last_row - first_row) * width) as usize];
+        let mut rng = rand::thread_rng();
+
+        for row in start..end {
+            let y = height - 1 - row;
+
+            for x in 0..width {
+                for (u, v) in pattern.generate(samples_per_pixel, &mut rng) {
+                    let (sample_x, sample_y) = (x as f32 + u - 0.5, y as f32 + v - 0.5);
+                    let color = compute_x_y_sample(sample_x, sample_y, &mut rng);
+
+                    for pixel_row in row.saturating_sub(reach)..(row + reach + 1).min(height) {
+                        let pixel_y = height - 1 - pixel_row;
+
+                        for pixel_x in x.saturating_sub(reach)..(x + reach + 1).min(width) {
+                            let weight = filter.weight(sample_x - pixel_x as f32, sample_y - pixel_y as f32);
+                            if weight != 0.0 {
+                                splats[((pixel_row - first_row) * width + pixel_x) as usize].add(color, weight);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        ((first_row * width) as usize, splats)
+    }).collect();
+
+    // bands overlap by the reach of the filter
+    let mut splats = vec![Splat::new(); (width * height) as usize];
+    for (offset, band) in bands {
+        for (splat, band_splat) in splats[offset..].iter_mut().zip(&band) {
+            splat.merge(band_splat);
+        }
+    }
 
-    pixels
+    framebuffer.pixels.par_iter_mut().zip(splats.par_iter()).for_each(|(pixel, splat)| {
+        *pixel = splat.pixel();
+    });
 }
 
-pub fn render(scene: &mut Scene, display: &mut Display) {
+pub fn render(scene: &mut Scene, settings: &RenderSettings, target: &mut impl RenderTarget) {
     let t_start = std::time::Instant::now();
 
-    let points = compute(scene, display);
+    scene.update_bvh();
+
+    let mut framebuffer = Framebuffer::new(target.width(), target.height());
+    compute(scene, settings, &mut framebuffer);
 
     let t_compute_ms = t_start.elapsed().as_millis();
 
-    display.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    target.draw(&framebuffer);
 
     let t_display_ms = t_start.elapsed().as_millis() - t_compute_ms;
 
-    display.canvas.clear();
-
     println!("Compute: {}ms, Display: {}ms", t_compute_ms, t_display_ms);
+}
 
-    for list_a in &points {
-        for list_b in list_a {
-            for point in list_b {
-                display.canvas.set_draw_color(point.color);
-                display.canvas.draw_point(point.point).unwrap();
-            }
-        }
-    }
+/// Adds one frame of jittered samples to `accumulator` and displays the average of all the frames
+/// rendered since the camera last moved.
+pub fn render_progressive(
+    scene: &mut Scene,
+    settings: &RenderSettings,
+    accumulator: &mut Accumulator,
+    target: &mut impl RenderTarget,
+) {
+    let t_start = std::time::Instant::now();
+
+    scene.update_bvh();
+    accumulator.accumulate(scene, settings);
+
+    let t_compute_ms = t_start.elapsed().as_millis();
+
+    target.draw(accumulator.framebuffer());
+
+    let t_display_ms = t_start.elapsed().as_millis() - t_compute_ms;
+
+    println!(
+        "Compute: {}ms, Display: {}ms, Frames: {}",
+        t_compute_ms, t_display_ms, accumulator.frames(),
+    );
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::math::Vec3;
+    use crate::math::{Transform, Vec3};
     use crate::render::Intersection;
+    use crate::render::instance::Instance;
+    use crate::render::camera::Projection;
+    use crate::render::environment::Environment;
+    use crate::render::objects::{Camera, Diamond, Ray, Scene, Shape, Sphere, Light, Color};
+    use crate::render::material::{Dielectric, Mirror, Phong, Relief};
+    use crate::render::texture::Constant;
+    use crate::render::{compute, render, Filter, Framebuffer, Integrator, RenderSettings};
+    use crate::render::sampling::SamplePattern;
+    use std::sync::Arc;
 
     #[test]
     fn test_intersections_order() {
@@ -217,12 +466,16 @@ mod tests {
             point: Vec3::new(0.0, 0.0, 0.0),
             dist: 1.0,
             normal: Vec3::new(0.0, 0.0, 0.0),
+            uv: [0.0, 0.0],
+            tangent: Vec3::new(0.0, 0.0, 0.0),
         };
 
         let b = Intersection {
             point: Vec3::new(0.0, 0.0, 0.0),
             dist: 2.0,
             normal: Vec3::new(0.0, 0.0, 0.0),
+            uv: [0.0, 0.0],
+            tangent: Vec3::new(0.0, 0.0, 0.0),
         };
 
         let mut inters = [a, b];
@@ -230,4 +483,352 @@ mod tests {
         let nearest = Intersection::nearest(&mut inters);
         assert_eq!(nearest.unwrap().dist, 1.0);
     }
+
+    fn test_scene() -> Scene {
+        let origin = Vec3::new(0.0, 0.0, -50.0);
+        let screen = Diamond::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(8.0, 0.0, 0.0),
+            Vec3::new(0.0, 6.0, 0.0),
+        );
+
+        Scene::new(Camera::new(origin, screen))
+    }
+
+    #[test]
+    fn test_shape_uvs() {
+        let diamond = Diamond::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0));
+        let ray = Ray::new(Vec3::new(1.0, -0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = diamond.intersect(&ray).unwrap();
+        assert_eq!(hit.uv, [0.75, 0.25]);
+        assert_eq!(hit.tangent, Vec3::new(1.0, 0.0, 0.0));
+
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0);
+        let top = sphere.intersect(&Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+        assert!((top.uv[1] - 1.0).abs() < 1e-6);
+
+        // the equator, a quarter of a turn from the x axis
+        let side = sphere.intersect(&Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        assert!((side.uv[0] - 0.25).abs() < 1e-6 && (side.uv[1] - 0.5).abs() < 1e-6, "got {:?}", side.uv);
+        // v goes up along normal x tangent
+        let (_, bitangent) = side.tangent_frame();
+        assert!((bitangent - Vec3::new(0.0, 1.0, 0.0)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_headless_render() {
+        let mut scene = test_scene();
+        scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, 20.0), 5.0)));
+        // only lit by the sky
+        scene.environment = Environment::Constant(Color::new(1.0, 1.0, 1.0));
+
+        let mut framebuffer = Framebuffer::new(40, 30);
+        render(&mut scene, &RenderSettings::default(), &mut framebuffer);
+
+        assert_eq!(framebuffer.alpha(20, 15), 1.0, "the sphere should cover the center");
+        assert_eq!(framebuffer.alpha(0, 0), 0.0, "nothing should be hit in the corner");
+        assert!(framebuffer.color(20, 15).rgb.x > 0.0);
+    }
+
+    #[test]
+    fn test_bvh_render_matches_linear() {
+        let mut scene = test_scene();
+
+        for i in 0..10 {
+            for j in 0..10 {
+                let center = Vec3::new(i as f32 * 3.0 - 15.0, j as f32 * 3.0 - 15.0, 20.0 + (i + j) as f32);
+                scene.add_object(Box::new(Sphere::new(center, 1.5)));
+            }
+        }
+        scene.add_light(Light::point(Vec3::new(0.0, 20.0, -20.0), Color::new(1.0, 1.0, 1.0), 1600.0));
+
+        let mut linear = Framebuffer::new(40, 30);
+        compute(&scene, &RenderSettings::default(), &mut linear);
+
+        scene.build_bvh();
+        let mut accelerated = Framebuffer::new(40, 30);
+        compute(&scene, &RenderSettings::default(), &mut accelerated);
+
+        assert_eq!(linear, accelerated);
+    }
+
+    #[test]
+    fn test_objects_have_their_own_material() {
+        let mut scene = test_scene();
+        scene.add_object_with_material(
+            Box::new(Sphere::new(Vec3::new(-3.0, 0.0, 20.0), 2.5)),
+            Arc::new(Phong::new(Color::new(1.0, 0.0, 0.0))),
+        );
+        scene.add_object_with_material(
+            Box::new(Sphere::new(Vec3::new(3.0, 0.0, 20.0), 2.5)),
+            Arc::new(Phong::new(Color::new(0.0, 0.0, 1.0))),
+        );
+        scene.add_light(Light::point(Vec3::new(0.0, 0.0, -20.0), Color::new(1.0, 1.0, 1.0), 1600.0));
+
+        let mut framebuffer = Framebuffer::new(40, 30);
+        render(&mut scene, &RenderSettings::default(), &mut framebuffer);
+
+        let left = framebuffer.color(10, 15);
+        let right = framebuffer.color(30, 15);
+        assert!(left.rgb.x > 0.0 && left.rgb.z == 0.0, "left sphere should be red, got {:?}", left);
+        assert!(right.rgb.z > 0.0 && right.rgb.x == 0.0, "right sphere should be blue, got {:?}", right);
+    }
+
+    #[test]
+    fn test_normal_map_shading() {
+        let brightness = |relief: Option<Relief>| {
+            let mut scene = test_scene();
+            let wall = Diamond::new(Vec3::new(0.0, 0.0, 20.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 100.0, 0.0));
+            let material = Phong::new(Color::new(1.0, 1.0, 1.0));
+            let material = match relief {
+                Some(relief) => material.with_relief(relief),
+                None => material,
+            };
+            scene.add_object_with_material(Box::new(wall), Arc::new(material));
+            scene.add_light(Light::point(Vec3::new(0.0, 0.0, -20.0), Color::new(1.0, 1.0, 1.0), 1600.0));
+
+            let mut framebuffer = Framebuffer::new(40, 30);
+            render(&mut scene, &RenderSettings::default(), &mut framebuffer);
+            (framebuffer.color(5, 15).rgb.x, framebuffer.color(35, 15).rgb.x)
+        };
+
+        // the light is in front of the middle of the flat wall
+        let (left, right) = brightness(None);
+        assert!((left - right).abs() < 1e-4);
+
+        // normals leaning along the width face the light on one side only
+        let leaning = Relief::NormalMap(Arc::new(Constant(Color::new(0.8, 0.5, 0.9))));
+        let (left, right) = brightness(Some(leaning));
+        assert!((left - right).abs() > 0.1 * left.max(right), "got {} and {}", left, right);
+    }
+
+    #[test]
+    fn test_area_light_soft_shadow() {
+        let brightness = |light: Light| {
+            let mut scene = test_scene();
+            scene.add_object(Box::new(Diamond::new(Vec3::new(0.0, 0.0, 20.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 100.0, 0.0))));
+            // its shadow on the wall is twice as large, 4 by 4
+            scene.add_object(Box::new(Diamond::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0))));
+            scene.add_light(light);
+
+            let mut framebuffer = Framebuffer::new(40, 30);
+            render(&mut scene, &RenderSettings { light_samples: 64, ..Default::default() }, &mut framebuffer);
+            // on the wall, just inside the shadow of a point light
+            framebuffer.color(26, 15).rgb.x
+        };
+
+        let white = Color::new(1.0, 1.0, 1.0);
+        // both as bright seen from far away
+        let hard = brightness(Light::point(Vec3::new(0.0, 0.0, -20.0), white, 1600.0));
+        let soft = brightness(Light::sphere(Vec3::new(0.0, 0.0, -20.0), 5.0, white, 1600.0 / (std::f32::consts::PI * 25.0)));
+        assert!(soft > hard + 0.05, "expected a penumbra, got {} and {}", soft, hard);
+    }
+
+    #[test]
+    fn test_mirror_reflection() {
+        let mut scene = test_scene();
+        let mirror = Diamond::new(Vec3::new(0.0, 0.0, 20.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 100.0, 0.0));
+        scene.add_object_with_material(Box::new(mirror), Arc::new(Mirror::new(Color::new(1.0, 1.0, 1.0))));
+        // behind the camera, only visible through the mirror
+        scene.add_object_with_material(
+            Box::new(Sphere::new(Vec3::new(0.0, 0.0, -100.0), 10.0)),
+            Arc::new(Phong::new(Color::new(1.0, 0.0, 0.0))),
+        );
+        scene.add_light(Light::point(Vec3::new(0.0, 0.0, -70.0), Color::new(1.0, 1.0, 1.0), 1600.0));
+        scene.environment = Environment::Constant(Color::new(1.0, 1.0, 1.0));
+
+        let mut framebuffer = Framebuffer::new(40, 30);
+        render(&mut scene, &RenderSettings::default(), &mut framebuffer);
+
+        let center = framebuffer.color(20, 15);
+        assert!(center.rgb.x > 0.0 && center.rgb.y == 0.0, "expected the red sphere, got {:?}", center);
+        assert_eq!(framebuffer.color(0, 0), Color::new(1.0, 1.0, 1.0), "reflected ray sees the sky");
+
+        // without any bounce allowed, the mirror is black
+        render(&mut scene, &RenderSettings { max_depth: 0, ..Default::default() }, &mut framebuffer);
+        assert_eq!(framebuffer.color(20, 15), Color::black());
+    }
+
+    #[test]
+    fn test_glass_refraction() {
+        let mut scene = test_scene();
+        let wall = Diamond::new(Vec3::new(0.0, 0.0, 60.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 100.0, 0.0));
+        scene.add_object_with_material(Box::new(wall), Arc::new(Phong::new(Color::new(1.0, 0.0, 0.0))));
+        scene.add_object_with_material(
+            Box::new(Sphere::new(Vec3::new(0.0, 0.0, 20.0), 5.0)),
+            Arc::new(Dielectric::glass()),
+        );
+        // above, out of the way of the sphere
+        scene.add_light(Light::point(Vec3::new(0.0, 30.0, 40.0), Color::new(1.0, 1.0, 1.0), 1600.0));
+
+        let mut framebuffer = Framebuffer::new(40, 30);
+        render(&mut scene, &RenderSettings::default(), &mut framebuffer);
+
+        // straight through the center of the sphere, most of the light is transmitted
+        let through = framebuffer.color(20, 15);
+        let direct = framebuffer.color(0, 0);
+        assert!(through.rgb.x > 0.5 * direct.rgb.x, "expected the red wall, got {:?}", through);
+        assert_eq!(through.rgb.y, 0.0);
+    }
+
+    #[test]
+    fn test_environment_lighting() {
+        let mut scene = test_scene();
+        scene.add_object(Box::new(Diamond::new(Vec3::new(0.0, 0.0, 20.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 100.0, 0.0))));
+        scene.environment = Environment::Constant(Color::new(1.0, 1.0, 1.0));
+
+        // an unoccluded white wall reflects all the light of a uniform sky,
+        // the path tracer splitting it between light and material samples
+        for integrator in [Integrator::Whitted, Integrator::PathTracer] {
+            let mut framebuffer = Framebuffer::new(40, 30);
+            render(&mut scene, &RenderSettings { integrator, ..Default::default() }, &mut framebuffer);
+
+            let wall = framebuffer.color(20, 15);
+            assert!((wall.rgb.x - 1.0).abs() < 1e-3, "{:?}: got {:?}", integrator, wall);
+        }
+    }
+
+    #[test]
+    fn test_environment_backdrop() {
+        let mut scene = test_scene();
+        scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, 20.0), 5.0)));
+        scene.environment = Environment::gradient(Color::new(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0), Color::black());
+        scene.backdrop = true;
+
+        let mut framebuffer = Framebuffer::new(40, 30);
+        render(&mut scene, &RenderSettings::default(), &mut framebuffer);
+
+        // the corners miss the sphere
+        let top = framebuffer.color(0, 0);
+        let bottom = framebuffer.color(0, 29);
+        assert_eq!(framebuffer.alpha(0, 0), 1.0);
+        // looking slightly up, near the horizon
+        assert!(top.rgb.z > 0.99 && top.rgb.x > 0.5 && top.rgb.x < 1.0, "got {:?}", top);
+        assert_eq!(bottom, Color::black());
+    }
+
+    #[test]
+    fn test_path_tracer_indirect_lighting() {
+        let mut scene = test_scene();
+        let wall = Diamond::new(Vec3::new(0.0, 0.0, 40.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 100.0, 0.0));
+        let floor = Diamond::new(Vec3::new(0.0, -5.0, 0.0), Vec3::new(100.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 100.0));
+        scene.add_object(Box::new(wall));
+        scene.add_object(Box::new(floor));
+        // shadows the center of the wall, which is then only lit by the light bouncing off the floor
+        scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 5.0, 30.0), 2.0)));
+        scene.add_light(Light::point(Vec3::new(0.0, 10.0, 20.0), Color::new(1.0, 1.0, 1.0), 200.0));
+
+        let settings = RenderSettings {
+            integrator: Integrator::PathTracer,
+            samples_per_pixel: 16,
+            ..Default::default()
+        };
+        let mut framebuffer = Framebuffer::new(40, 30);
+        render(&mut scene, &settings, &mut framebuffer);
+
+        let shadowed = framebuffer.color(20, 15);
+        assert_eq!(framebuffer.alpha(20, 15), 1.0);
+        assert!(shadowed.rgb.x > 0.0 && shadowed.rgb.x.is_finite(), "got {:?}", shadowed);
+
+        // without any bounce, nothing emits light towards the camera
+        render(&mut scene, &RenderSettings { max_depth: 0, ..settings }, &mut framebuffer);
+        assert_eq!(framebuffer.color(20, 15), Color::black());
+    }
+
+    #[test]
+    fn test_antialiasing() {
+        let mut scene = test_scene();
+        scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, 20.0), 5.0)));
+        let partially_covered = |framebuffer: &Framebuffer| {
+            framebuffer.pixels.iter().filter(|pixel| pixel[3] > 0.0 && pixel[3] < 1.0).count()
+        };
+
+        let mut aliased = Framebuffer::new(40, 30);
+        render(&mut scene, &RenderSettings::default(), &mut aliased);
+        assert_eq!(partially_covered(&aliased), 0);
+
+        for pattern in [SamplePattern::Grid, SamplePattern::Stratified, SamplePattern::Sobol] {
+            for filter in [Filter::Box, Filter::Tent { radius: 1.0 }, Filter::Gaussian { sigma: 0.5 }, Filter::mitchell()] {
+                let settings = RenderSettings { samples_per_pixel: 16, pattern, filter, ..Default::default() };
+                let mut framebuffer = Framebuffer::new(40, 30);
+                render(&mut scene, &settings, &mut framebuffer);
+
+                assert!(partially_covered(&framebuffer) > 0, "{:?} {:?}", pattern, filter);
+                assert_eq!(framebuffer.alpha(20, 15), 1.0, "{:?} {:?}", pattern, filter);
+                assert_eq!(framebuffer.alpha(0, 0), 0.0, "{:?} {:?}", pattern, filter);
+            }
+        }
+    }
+
+    #[test]
+    fn test_depth_of_field() {
+        let camera = Camera::look_at(
+            Vec3::new(0.0, 0.0, -50.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            30.0,
+            4.0 / 3.0,
+        );
+        let mut scene = Scene::new(camera.with_lens(2.0, 50.0));
+        // in focus on the left, far behind the focal plane on the right
+        scene.add_object(Box::new(Sphere::new(Vec3::new(-6.0, 0.0, 0.0), 4.0)));
+        scene.add_object(Box::new(Sphere::new(Vec3::new(24.0, 0.0, 100.0), 12.0)));
+
+        let settings = RenderSettings { samples_per_pixel: 64, pattern: SamplePattern::Stratified, ..Default::default() };
+        let mut framebuffer = Framebuffer::new(40, 30);
+        render(&mut scene, &settings, &mut framebuffer);
+
+        // count the pixels of each half that are neither fully covered nor empty
+        let blurred = |columns: std::ops::Range<u32>| {
+            columns.flat_map(|x| (0..30).map(move |y| (x, y)))
+                .filter(|&(x, y)| framebuffer.alpha(x, y) > 0.05 && framebuffer.alpha(x, y) < 0.95)
+                .count()
+        };
+        let sharp_edges = blurred(0..20);
+        let blurred_edges = blurred(20..40);
+        assert!(blurred_edges > 2 * sharp_edges, "sharp: {}, blurred: {}", sharp_edges, blurred_edges);
+    }
+
+    #[test]
+    fn test_orthographic_render_ignores_distance() {
+        let coverage = |distance: f32| {
+            let mut scene = test_scene();
+            scene.camera.set_projection(Projection::Orthographic);
+            scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, distance), 2.0)));
+
+            let mut framebuffer = Framebuffer::new(40, 30);
+            render(&mut scene, &RenderSettings::default(), &mut framebuffer);
+            framebuffer.pixels.iter().filter(|pixel| pixel[3] > 0.0).count()
+        };
+
+        // half the width of the 8 units wide screen, whatever the distance
+        let near = coverage(20.0);
+        assert!(near > 0);
+        assert_eq!(near, coverage(500.0));
+    }
+
+    #[test]
+    fn test_motion_blur() {
+        let partially_covered = |shutter_close: f32| {
+            let mut scene = test_scene();
+            scene.camera.set_shutter(0.0, shutter_close);
+            scene.add_object(Box::new(Instance::animated(
+                Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 2.0)),
+                Transform::translation(&Vec3::new(-3.0, 0.0, 20.0)),
+                0.0,
+                Transform::translation(&Vec3::new(3.0, 0.0, 20.0)),
+                1.0,
+            )));
+
+            let settings = RenderSettings { samples_per_pixel: 16, pattern: SamplePattern::Stratified, ..Default::default() };
+            let mut framebuffer = Framebuffer::new(40, 30);
+            render(&mut scene, &settings, &mut framebuffer);
+            framebuffer.pixels.iter().filter(|pixel| pixel[3] > 0.05 && pixel[3] < 0.95).count()
+        };
+
+        // the sphere smears along its path while the shutter is open
+        let still = partially_covered(0.0);
+        let moving = partially_covered(1.0);
+        assert!(moving > 2 * still, "still: {}, moving: {}", still, moving);
+    }
 }
@@ -0,0 +1,134 @@
+use super::objects::Scene;
+use super::sampling::SamplePattern;
+use super::{compute_samples, Framebuffer, RenderSettings};
+
+/// Running average of successive renders of a still view, for the image to converge
+/// while the camera doesn't move.
+///
+/// Every frame traces rays through random points of the pixels, so that the average
+/// is anti-aliased and noise from the path tracer fades out.
+/// The accumulation starts over whenever the camera is translated or rotated.
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    /// Sum of the frames, colours weighted by their alpha.
+    sum: Vec<[f32; 4]>,
+    frames: u32,
+    /// Camera generation the accumulated frames were rendered from.
+    generation: Option<u64>,
+    frame: Framebuffer,
+    average: Framebuffer,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Accumulator {
+        Accumulator {
+            sum: vec![[0.0; 4]; (width * height) as usize],
+            frames: 0,
+            generation: None,
+            frame: Framebuffer::new(width, height),
+            average: Framebuffer::new(width, height),
+        }
+    }
+
+    /// Number of frames in the average.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Drops the accumulated frames, for instance after the scene changed.
+    pub fn reset(&mut self) {
+        self.sum.fill([0.0; 4]);
+        self.frames = 0;
+        self.generation = None;
+        self.average.clear();
+    }
+
+    /// Renders one more frame of `scene` into the average, starting over if the camera moved.
+    pub fn accumulate(&mut self, scene: &Scene, settings: &RenderSettings) {
+        if self.generation != Some(scene.camera.generation()) {
+            self.reset();
+            self.generation = Some(scene.camera.generation());
+        }
+
+        // the same positions in every frame wouldn't converge to anything new
+        let pattern = match settings.pattern {
+            SamplePattern::Grid => SamplePattern::Stratified,
+            pattern => pattern,
+        };
+        compute_samples(scene, settings, pattern, &mut self.frame);
+        self.frames += 1;
+
+        let frames = self.frames as f32;
+
+        for ((sum, pixel), average) in self.sum.iter_mut().zip(&self.frame.pixels).zip(self.average.pixels.iter_mut()) {
+            let [r, g, b, a] = *pixel;
+            sum[0] += r * a;
+            sum[1] += g * a;
+            sum[2] += b * a;
+            sum[3] += a;
+
+            *average = if sum[3] > 0.0 {
+                [sum[0] / sum[3], sum[1] / sum[3], sum[2] / sum[3], sum[3] / frames]
+            } else {
+                [0.0; 4]
+            };
+        }
+    }
+
+    /// Average of the frames accumulated so far.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.average
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec3;
+    use crate::render::objects::{Camera, Diamond, Sphere};
+
+    fn scene() -> Scene {
+        let screen = Diamond::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(8.0, 0.0, 0.0), Vec3::new(0.0, 6.0, 0.0));
+        let mut scene = Scene::new(Camera::new(Vec3::new(0.0, 0.0, -50.0), screen));
+        scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, 20.0), 5.0)));
+        scene
+    }
+
+    #[test]
+    fn test_accumulation_resets_when_camera_moves() {
+        let mut scene = scene();
+        let settings = RenderSettings::default();
+        let mut accumulator = Accumulator::new(40, 30);
+
+        accumulator.accumulate(&scene, &settings);
+        accumulator.accumulate(&scene, &settings);
+        assert_eq!(accumulator.frames(), 2);
+
+        scene.camera.translate(0.0, 0.0, 1.0);
+        accumulator.accumulate(&scene, &settings);
+        assert_eq!(accumulator.frames(), 1);
+
+        scene.camera.rotate(0.0, 0.01, 0.0);
+        accumulator.accumulate(&scene, &settings);
+        assert_eq!(accumulator.frames(), 1);
+    }
+
+    #[test]
+    fn test_accumulated_edges_are_smoothed() {
+        let scene = scene();
+        let settings = RenderSettings::default();
+        let mut accumulator = Accumulator::new(40, 30);
+
+        for _ in 0..64 {
+            accumulator.accumulate(&scene, &settings);
+        }
+
+        let average = accumulator.framebuffer();
+        assert_eq!(average.alpha(20, 15), 1.0);
+        assert_eq!(average.alpha(0, 0), 0.0);
+
+        // pixels on the outline of the sphere are only partially covered
+        let partial = average.pixels.iter().filter(|pixel| pixel[3] > 0.0 && pixel[3] < 1.0).count();
+        assert!(partial > 0);
+    }
+}
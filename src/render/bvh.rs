@@ -0,0 +1,403 @@
+use crate::math::Vec3;
+
+use super::objects::{Intersection, Ray};
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Box containing nothing, the neutral element of `union`.
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points.iter().fold(Aabb::empty(), |aabb, point| aabb.grow(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(&other.min),
+            max: self.max.max(&other.max),
+        }
+    }
+
+    pub fn grow(&self, point: &Vec3) -> Aabb {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Distance at which `ray` enters the box, if it does so before `t_max`.
+    /// `inv_direction` is the component-wise inverse of `ray.direction`.
+    pub fn hit(&self, ray: &Ray, inv_direction: &Vec3, t_max: f32) -> Option<f32> {
+        let mut t_near = 0.0f32;
+        let mut t_far = t_max;
+
+        for axis in 0..3 {
+            let t_a = (self.min[axis] - ray.origin[axis]) * inv_direction[axis];
+            let t_b = (self.max[axis] - ray.origin[axis]) * inv_direction[axis];
+
+            t_near = t_near.max(t_a.min(t_b));
+            t_far = t_far.min(t_a.max(t_b));
+        }
+
+        if t_near <= t_far {
+            Some(t_near)
+        } else {
+            None
+        }
+    }
+}
+
+const BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// cost of visiting a node relative to the cost of intersecting a primitive
+const TRAVERSAL_COST: f32 = 1.0;
+
+/// Leaves have `count > 0` primitives starting at `first` in `Bvh::indices`.
+/// Interior nodes have `count == 0`, their left child directly follows them
+/// and their right child is at index `first`.
+#[derive(Debug, Clone)]
+struct Node {
+    bounds: Aabb,
+    first: usize,
+    count: usize,
+}
+
+/// Bounding volume hierarchy over a list of primitives, built with the surface area heuristic.
+///
+/// The hierarchy only knows about the bounding boxes of the primitives, which are
+/// referred to by their index in the slice given to `Bvh::build`.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+struct Split {
+    axis: usize,
+    bin: usize,
+    cost: f32,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: (0..bounds.len()).collect(),
+        };
+
+        if !bounds.is_empty() {
+            let centers: Vec<Vec3> = bounds.iter().map(|aabb| aabb.center()).collect();
+            bvh.build_node(bounds, &centers, 0, bounds.len());
+        }
+
+        bvh
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::empty(), |node| node.bounds)
+    }
+
+    fn build_node(&mut self, bounds: &[Aabb], centers: &[Vec3], first: usize, count: usize) -> usize {
+        let items = &self.indices[first..first + count];
+        let node_bounds = items.iter().fold(Aabb::empty(), |aabb, &i| aabb.union(&bounds[i]));
+        let centers_bounds = items.iter().fold(Aabb::empty(), |aabb, &i| aabb.grow(&centers[i]));
+
+        let index = self.nodes.len();
+        self.nodes.push(Node { bounds: node_bounds, first, count });
+
+        if count == 1 {
+            return index;
+        }
+
+        let split = match Bvh::find_split(bounds, centers, items, &centers_bounds) {
+            Some(split) => split,
+            None => return index,
+        };
+
+        let split_cost = TRAVERSAL_COST + split.cost / node_bounds.surface_area().max(f32::EPSILON);
+        if count <= MAX_LEAF_SIZE && split_cost >= count as f32 {
+            return index;
+        }
+
+        let lo = centers_bounds.min[split.axis];
+        let extent = centers_bounds.max[split.axis] - lo;
+
+        // partition the primitives in place: those falling in a bin left of the split first
+        let items = &mut self.indices[first..first + count];
+        let mut left_count = 0;
+        for i in 0..count {
+            if bin_index(centers[items[i]][split.axis], lo, extent) < split.bin {
+                items.swap(left_count, i);
+                left_count += 1;
+            }
+        }
+
+        self.build_node(bounds, centers, first, left_count);
+        let right = self.build_node(bounds, centers, first + left_count, count - left_count);
+
+        self.nodes[index].first = right;
+        self.nodes[index].count = 0;
+
+        index
+    }
+
+    /// Cheapest split between bins according to the surface area heuristic.
+    fn find_split(bounds: &[Aabb], centers: &[Vec3], items: &[usize], centers_bounds: &Aabb) -> Option<Split> {
+        let mut best: Option<Split> = None;
+
+        for axis in 0..3 {
+            let lo = centers_bounds.min[axis];
+            let extent = centers_bounds.max[axis] - lo;
+
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bins = [(Aabb::empty(), 0usize); BINS];
+            for &i in items {
+                let bin = &mut bins[bin_index(centers[i][axis], lo, extent)];
+                bin.0 = bin.0.union(&bounds[i]);
+                bin.1 += 1;
+            }
+
+            // right_costs[k] is the cost of everything in bins k + 1 and above
+            let mut right_costs = [0.0; BINS - 1];
+            let mut right_counts = [0; BINS - 1];
+            let mut aabb = Aabb::empty();
+            let mut count = 0;
+            for k in (1..BINS).rev() {
+                aabb = aabb.union(&bins[k].0);
+                count += bins[k].1;
+                right_costs[k - 1] = aabb.surface_area() * count as f32;
+                right_counts[k - 1] = count;
+            }
+
+            let mut aabb = Aabb::empty();
+            let mut count = 0;
+            for k in 0..BINS - 1 {
+                aabb = aabb.union(&bins[k].0);
+                count += bins[k].1;
+
+                if count == 0 || right_counts[k] == 0 {
+                    continue;
+                }
+
+                let cost = aabb.surface_area() * count as f32 + right_costs[k];
+                if best.as_ref().is_none_or(|best| cost < best.cost) {
+                    best = Some(Split { axis, bin: k + 1, cost });
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Nearest intersection along `ray`, `intersect` is called with the index
+    /// of every primitive whose bounding box is crossed by the ray.
+    pub fn intersect<F>(&self, ray: &Ray, mut intersect: F) -> Option<(usize, Intersection)>
+    where
+        F: FnMut(usize) -> Option<Intersection>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inv_direction = inverse(&ray.direction);
+        let mut nearest: Option<(usize, Intersection)> = None;
+        let mut t_max = f32::INFINITY;
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if node.bounds.hit(ray, &inv_direction, t_max).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &i in &self.indices[node.first..node.first + node.count] {
+                    if let Some(intersection) = intersect(i) {
+                        if intersection.dist >= 0.0 && intersection.dist < t_max {
+                            t_max = intersection.dist;
+                            nearest = Some((i, intersection));
+                        }
+                    }
+                }
+            } else {
+                let left = index + 1;
+                let right = node.first;
+
+                let t_left = self.nodes[left].bounds.hit(ray, &inv_direction, t_max);
+                let t_right = self.nodes[right].bounds.hit(ray, &inv_direction, t_max);
+
+                // push the farthest child first so that the nearest one is visited first
+                match (t_left, t_right) {
+                    (Some(t_left), Some(t_right)) => {
+                        if t_left < t_right {
+                            stack.push(right);
+                            stack.push(left);
+                        } else {
+                            stack.push(left);
+                            stack.push(right);
+                        }
+                    },
+                    (Some(_), None) => stack.push(left),
+                    (None, Some(_)) => stack.push(right),
+                    (None, None) => {},
+                }
+            }
+        }
+
+        nearest
+    }
+
+    /// Whether `hit` returns true for any primitive whose bounding box
+    /// is crossed by `ray` before `t_max`, used for shadow rays.
+    pub fn any<F>(&self, ray: &Ray, t_max: f32, mut hit: F) -> bool
+    where
+        F: FnMut(usize) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let inv_direction = inverse(&ray.direction);
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if node.bounds.hit(ray, &inv_direction, t_max).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                if self.indices[node.first..node.first + node.count].iter().any(|&i| hit(i)) {
+                    return true;
+                }
+            } else {
+                stack.push(node.first);
+                stack.push(index + 1);
+            }
+        }
+
+        false
+    }
+}
+
+fn bin_index(value: f32, lo: f32, extent: f32) -> usize {
+    (((value - lo) / extent * BINS as f32) as usize).min(BINS - 1)
+}
+
+fn inverse(v: &Vec3) -> Vec3 {
+    Vec3::new(1.0 / v.x, 1.0 / v.y, 1.0 / v.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::objects::{Shape, Sphere};
+    use rand::Rng;
+
+    #[test]
+    fn test_aabb_hit() {
+        let aabb = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let inv_direction = inverse(&ray.direction);
+
+        assert_eq!(aabb.hit(&ray, &inv_direction, f32::INFINITY), Some(4.0));
+        assert_eq!(aabb.hit(&ray, &inv_direction, 3.0), None, "box is farther than t_max");
+
+        let ray = Ray::new(Vec3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(aabb.hit(&ray, &inverse(&ray.direction), f32::INFINITY), None);
+    }
+
+    #[test]
+    fn test_aabb_surface_area() {
+        let aabb = Aabb::from_points(&[Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 3.0)]);
+        assert_eq!(aabb.surface_area(), 22.0);
+        assert_eq!(Aabb::empty().surface_area(), 0.0);
+    }
+
+    #[test]
+    fn test_bvh_matches_brute_force() {
+        let mut rng = rand::thread_rng();
+
+        let spheres: Vec<Sphere> = (0..500).map(|_| {
+            Sphere::new(
+                Vec3::new(rng.gen_range(-50.0..50.0), rng.gen_range(-50.0..50.0), rng.gen_range(-50.0..50.0)),
+                rng.gen_range(0.5..3.0),
+            )
+        }).collect();
+
+        let bounds: Vec<Aabb> = spheres.iter().map(|sphere| sphere.bounding_box()).collect();
+        let bvh = Bvh::build(&bounds);
+        assert_eq!(bvh.len(), spheres.len());
+
+        for _ in 0..200 {
+            let origin = Vec3::new(rng.gen_range(-80.0..80.0), rng.gen_range(-80.0..80.0), -100.0);
+            let target = Vec3::new(rng.gen_range(-50.0..50.0), rng.gen_range(-50.0..50.0), 0.0);
+            let ray = Ray::new(origin, target - origin);
+
+            let expected = spheres.iter()
+                .filter_map(|sphere| sphere.intersect(&ray))
+                .map(|intersection| intersection.dist)
+                .fold(None, |min: Option<f32>, dist| Some(min.map_or(dist, |min| min.min(dist))));
+
+            let actual = bvh.intersect(&ray, |i| spheres[i].intersect(&ray))
+                .map(|(_, intersection)| intersection.dist);
+
+            assert_eq!(actual, expected);
+            assert_eq!(bvh.any(&ray, f32::INFINITY, |i| spheres[i].intersect(&ray).is_some()), expected.is_some());
+        }
+    }
+
+    #[test]
+    fn test_empty_bvh() {
+        let bvh = Bvh::build(&[]);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(bvh.is_empty());
+        assert!(bvh.intersect(&ray, |_| None).is_none());
+        assert!(!bvh.any(&ray, f32::INFINITY, |_| true));
+    }
+}
@@ -0,0 +1,414 @@
+use std::f32::consts::PI;
+
+use crate::math::{Quat, Vec3};
+
+use super::objects::{Diamond, Ray, Shape};
+use super::sampling;
+
+/// How the camera maps the image to rays.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    /// Rays leave the camera position and go through the screen.
+    #[default]
+    Perspective,
+    /// Parallel rays along the viewing direction, leaving from a rectangle the size of the screen
+    /// centered on the camera position.
+    Orthographic,
+    /// Equidistant fisheye, the angle to the viewing direction is proportional to the distance
+    /// to the center of the image. The image circle has the height of the image and spans `fov_degrees`.
+    Fisheye { fov_degrees: f32 },
+    /// Full 360° by 180° panorama, longitude along the width of the image and latitude along its height.
+    Equirectangular,
+}
+
+/// Rays leave `position` and go through the `screen` rectangle.
+///
+/// The screen holds the whole frame of the camera: its center gives the viewing direction,
+/// `width` and `height` the right and up directions as well as the field of view.
+///
+/// By default the camera is a pinhole and everything is sharp. Giving it an aperture turns it
+/// into a thin lens: rays start anywhere on the lens disk and only the objects at the focus
+/// distance are in focus.
+#[derive(Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    pub screen: Diamond,
+    projection: Projection,
+    aperture: f32,
+    focus_distance: f32,
+    shutter: (f32, f32),
+    generation: u64,
+}
+
+impl Camera {
+    pub fn new(position: Vec3, screen: Diamond) -> Camera {
+        let focus_distance = (screen.center - position).norm();
+        Camera {
+            position,
+            screen,
+            projection: Projection::Perspective,
+            aperture: 0.0,
+            focus_distance,
+            shutter: (0.0, 0.0),
+            generation: 0,
+        }
+    }
+
+    /// Camera at `eye` looking at `target`, `up` being roughly the upward direction of the image.
+    /// `vfov_degrees` is the vertical field of view and `aspect` the width over height ratio.
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3, vfov_degrees: f32, aspect: f32) -> Camera {
+        let forward = (target - eye).normalize();
+        let right = up.cross_product(&forward).normalize();
+        let up = forward.cross_product(&right);
+
+        let height = 2.0 * (vfov_degrees.to_radians() / 2.0).tan();
+        let screen = Diamond::new(eye + forward, height * aspect * right, height * up);
+
+        Camera::new(eye, screen)
+    }
+
+    /// Unit vector the camera looks along.
+    pub fn forward(&self) -> Vec3 {
+        (self.screen.center - self.position).normalize()
+    }
+
+    /// Unit vector pointing to the right of the image.
+    pub fn right(&self) -> Vec3 {
+        self.screen.width.normalize()
+    }
+
+    /// Unit vector pointing to the top of the image.
+    pub fn up(&self) -> Vec3 {
+        self.screen.height.normalize()
+    }
+
+    fn focal_length(&self) -> f32 {
+        (self.screen.center - self.position).dot(&self.forward())
+    }
+
+    pub fn vfov_degrees(&self) -> f32 {
+        (2.0 * (self.screen.height.norm() / 2.0 / self.focal_length()).atan()).to_degrees()
+    }
+
+    /// Changes the vertical field of view, keeping the aspect ratio.
+    pub fn set_vfov_degrees(&mut self, vfov_degrees: f32) {
+        let height = 2.0 * self.focal_length() * (vfov_degrees.to_radians() / 2.0).tan();
+        let aspect = self.aspect();
+
+        self.screen.height = height * self.up();
+        self.screen.width = height * aspect * self.right();
+        self.generation += 1;
+    }
+
+    /// Width over height of the image.
+    pub fn aspect(&self) -> f32 {
+        self.screen.width.norm() / self.screen.height.norm()
+    }
+
+    /// Changes the width of the image, keeping the vertical field of view.
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.screen.width = self.screen.height.norm() * aspect * self.right();
+        self.generation += 1;
+    }
+
+    /// Fits the aspect ratio to an image of `width` by `height` pixels.
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        self.set_aspect(width as f32 / height as f32);
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.generation += 1;
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Camera {
+        self.projection = projection;
+        self
+    }
+
+    /// Radius of the lens, zero for a pinhole camera.
+    pub fn aperture(&self) -> f32 {
+        self.aperture
+    }
+
+    pub fn set_aperture(&mut self, aperture: f32) {
+        self.aperture = aperture;
+        self.generation += 1;
+    }
+
+    /// Distance from the lens to the plane in focus.
+    pub fn focus_distance(&self) -> f32 {
+        self.focus_distance
+    }
+
+    pub fn set_focus_distance(&mut self, focus_distance: f32) {
+        self.focus_distance = focus_distance;
+        self.generation += 1;
+    }
+
+    /// Sets the lens in one go, the camera being built.
+    pub fn with_lens(mut self, aperture: f32, focus_distance: f32) -> Camera {
+        self.aperture = aperture;
+        self.focus_distance = focus_distance;
+        self
+    }
+
+    /// Times at which the shutter opens and closes, rays are spread over this interval
+    /// so that moving objects are blurred. Both are zero by default.
+    pub fn shutter(&self) -> (f32, f32) {
+        self.shutter
+    }
+
+    pub fn set_shutter(&mut self, open: f32, close: f32) {
+        self.shutter = (open, close);
+        self.generation += 1;
+    }
+
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Camera {
+        self.shutter = (open, close);
+        self
+    }
+
+    /// Instant of the shutter interval picked by `u` in [0, 1).
+    pub fn shutter_time(&self, u: f32) -> f32 {
+        let (open, close) = self.shutter;
+        open + u * (close - open)
+    }
+
+    /// Ray through the point (x, y) of the image, (0, 0) being its bottom left corner and (1, 1) the top right one,
+    /// leaving from the center of the lens.
+    /// `None` for the points outside of the image circle of a fisheye.
+    pub fn ray(&self, x: f32, y: f32) -> Option<Ray> {
+        let (dx, dy) = (x - 0.5, y - 0.5);
+
+        let ray = match self.projection {
+            Projection::Perspective => {
+                let screen_pos = self.screen.center + dx * self.screen.width + dy * self.screen.height;
+                Ray::new(self.position, screen_pos - self.position)
+            },
+            Projection::Orthographic => {
+                let origin = self.position + dx * self.screen.width + dy * self.screen.height;
+                Ray::new(origin, self.forward())
+            },
+            Projection::Fisheye { fov_degrees } => {
+                // distance to the center, 1 on the image circle
+                let (u, v) = (2.0 * dx * self.aspect(), 2.0 * dy);
+                let r = (u * u + v * v).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+
+                let theta = r * fov_degrees.to_radians() / 2.0;
+                let (u, v) = if r > 0.0 { (u / r, v / r) } else { (0.0, 0.0) };
+                let direction = theta.cos() * self.forward() + theta.sin() * (u * self.right() + v * self.up());
+                Ray::new(self.position, direction)
+            },
+            Projection::Equirectangular => {
+                let longitude = 2.0 * PI * dx;
+                let latitude = PI * dy;
+                let direction = latitude.cos() * (longitude.sin() * self.right() + longitude.cos() * self.forward())
+                    + latitude.sin() * self.up();
+                Ray::new(self.position, direction)
+            },
+        };
+
+        Some(ray)
+    }
+
+    /// Same as `ray`, leaving from the point of the lens picked by (lens_u, lens_v) in [0, 1)².
+    /// Only perspective cameras have a lens, the other projections are always sharp.
+    pub fn lens_ray(&self, x: f32, y: f32, lens_u: f32, lens_v: f32) -> Option<Ray> {
+        let ray = self.ray(x, y)?;
+        if self.aperture <= 0.0 || self.projection != Projection::Perspective {
+            return Some(ray);
+        }
+
+        // every ray through the same pixel converges on the focal plane
+        let focus_point = ray.origin + (self.focus_distance / ray.direction.dot(&self.forward())) * ray.direction;
+
+        let (lens_x, lens_y) = sampling::concentric_disk(lens_u, lens_v);
+        let origin = self.position + self.aperture * (lens_x * self.right() + lens_y * self.up());
+
+        Some(Ray::new(origin, focus_point - origin))
+    }
+
+    /// Euler angles rotation, same as `Mat3::rot_x_y_z`.
+    pub fn rotate(&mut self, theta_x: f32, theta_y: f32, theta_z: f32) {
+        self.rotate_quat(&Quat::from_euler(theta_x, theta_y, theta_z));
+    }
+
+    /// Turns the camera around its position.
+    pub fn rotate_quat(&mut self, rotation: &Quat) {
+        let rotation = rotation.normalize();
+        self.screen.center = self.position + rotation.rotate(&(self.screen.center - self.position));
+        self.screen.width = rotation.rotate(&self.screen.width);
+        self.screen.height = rotation.rotate(&self.screen.height);
+        self.generation += 1;
+    }
+
+    pub fn translate(&mut self, d_pos_x: f32, d_pos_y: f32, d_pos_z: f32) {
+        let d_pos = Vec3::new(d_pos_x, d_pos_y, d_pos_z);
+        self.position += d_pos;
+        self.screen.translate(&d_pos);
+        self.generation += 1;
+    }
+
+    /// Number of times the camera has been moved through `translate` and `rotate`,
+    /// used to tell when accumulated frames are out of date.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).norm() < 1e-5, "expected {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn test_look_at_basis() {
+        let camera = Camera::look_at(
+            Vec3::new(0.0, 0.0, -10.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.5),
+            90.0,
+            2.0,
+        );
+
+        assert_close(camera.forward(), Vec3::new(0.0, 0.0, 1.0));
+        assert_close(camera.right(), Vec3::new(1.0, 0.0, 0.0));
+        assert_close(camera.up(), Vec3::new(0.0, 1.0, 0.0));
+        assert!((camera.vfov_degrees() - 90.0).abs() < 1e-3);
+        assert!((camera.aspect() - 2.0).abs() < 1e-5);
+
+        assert_close(camera.ray(0.5, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, 1.0));
+        // 45 degrees up at the top of the image, twice as wide horizontally
+        assert_close(camera.ray(0.5, 1.0).unwrap().direction, Vec3::new(0.0, 1.0, 1.0).normalize());
+        assert_close(camera.ray(1.0, 0.5).unwrap().direction, Vec3::new(2.0, 0.0, 1.0).normalize());
+        assert_close(camera.ray(0.0, 0.0).unwrap().origin, Vec3::new(0.0, 0.0, -10.0));
+    }
+
+    #[test]
+    fn test_change_fov_and_resolution() {
+        let mut camera = Camera::look_at(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(4.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.5,
+        );
+        let forward = camera.forward();
+
+        camera.set_vfov_degrees(60.0);
+        assert!((camera.vfov_degrees() - 60.0).abs() < 1e-3);
+        assert!((camera.aspect() - 1.5).abs() < 1e-5);
+
+        camera.set_resolution(800, 200);
+        assert!((camera.aspect() - 4.0).abs() < 1e-5);
+        assert!((camera.vfov_degrees() - 60.0).abs() < 1e-3);
+        assert_close(camera.forward(), forward);
+        assert_eq!(camera.generation(), 2);
+    }
+
+    #[test]
+    fn test_rotation_turns_around_the_eye() {
+        let mut camera = Camera::look_at(
+            Vec3::new(5.0, 0.0, 0.0),
+            Vec3::new(5.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            1.0,
+        );
+
+        camera.rotate_quat(&Quat::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), std::f32::consts::PI / 2.0));
+
+        assert_close(camera.position, Vec3::new(5.0, 0.0, 0.0));
+        assert_close(camera.forward(), Vec3::new(1.0, 0.0, 0.0));
+        assert_close(camera.up(), Vec3::new(0.0, 1.0, 0.0));
+        assert!((camera.vfov_degrees() - 60.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_lens_rays_converge_on_the_focal_plane() {
+        let camera = Camera::look_at(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            1.0,
+        ).with_lens(0.5, 10.0);
+
+        for (x, y) in [(0.5, 0.5), (0.1, 0.8)] {
+            let pinhole = camera.ray(x, y).unwrap();
+            let expected = pinhole.origin + (10.0 / pinhole.direction.z) * pinhole.direction;
+
+            for (u, v) in [(0.0, 0.0), (0.9, 0.3), (0.5, 0.99)] {
+                let ray = camera.lens_ray(x, y, u, v).unwrap();
+                assert!(ray.origin.z.abs() < 1e-6 && ray.origin.norm() <= 0.5 + 1e-5);
+
+                let focus_point = ray.origin + ((10.0 - ray.origin.z) / ray.direction.z) * ray.direction;
+                assert_close(focus_point, expected);
+            }
+        }
+    }
+
+    fn camera(projection: Projection) -> Camera {
+        Camera::look_at(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+        ).with_projection(projection)
+    }
+
+    #[test]
+    fn test_orthographic_projection() {
+        let camera = camera(Projection::Orthographic);
+
+        for (x, y) in [(0.0, 0.0), (0.5, 0.5), (1.0, 0.25)] {
+            assert_close(camera.ray(x, y).unwrap().direction, Vec3::new(0.0, 0.0, 1.0));
+        }
+        // the screen is 4 by 2
+        assert_close(camera.ray(1.0, 0.25).unwrap().origin, Vec3::new(2.0, -0.5, 0.0));
+    }
+
+    #[test]
+    fn test_fisheye_projection() {
+        let camera = camera(Projection::Fisheye { fov_degrees: 180.0 });
+
+        assert_close(camera.ray(0.5, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, 1.0));
+        // the edge of the image circle looks sideways, halfway to it looks at 45 degrees
+        assert_close(camera.ray(0.5, 1.0).unwrap().direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_close(camera.ray(0.625, 0.5).unwrap().direction, Vec3::new(1.0, 0.0, 1.0).normalize());
+        assert!(camera.ray(0.0, 0.5).is_none(), "outside of the image circle");
+    }
+
+    #[test]
+    fn test_equirectangular_projection() {
+        let camera = camera(Projection::Equirectangular);
+
+        assert_close(camera.ray(0.5, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, 1.0));
+        assert_close(camera.ray(0.75, 0.5).unwrap().direction, Vec3::new(1.0, 0.0, 0.0));
+        assert_close(camera.ray(0.0, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, -1.0));
+        assert_close(camera.ray(0.3, 1.0).unwrap().direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_close(camera.ray(0.5, 0.25).unwrap().direction, Vec3::new(0.0, -1.0, 1.0).normalize());
+    }
+
+    #[test]
+    fn test_screen_constructor() {
+        let screen = Diamond::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(8.0, 0.0, 0.0), Vec3::new(0.0, 6.0, 0.0));
+        let camera = Camera::new(Vec3::new(0.0, 0.0, -50.0), screen);
+
+        assert!((camera.aspect() - 8.0 / 6.0).abs() < 1e-5);
+        assert_close(camera.ray(0.5, 0.5).unwrap().direction, Vec3::new(0.0, 0.0, 1.0));
+        assert_close(camera.ray(1.0, 1.0).unwrap().direction, Vec3::new(4.0, 3.0, 50.0).normalize());
+    }
+}
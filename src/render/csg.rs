@@ -0,0 +1,231 @@
+use crate::math::{Quat, Vec3};
+
+use super::bvh::Aabb;
+use super::objects::{Intersection, Interval, Ray, Shape};
+
+/// How the volumes of the two shapes of a `Csg` are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Inside either shape.
+    Union,
+    /// Inside both shapes.
+    Intersection,
+    /// Inside the first shape but not the second one.
+    Difference,
+}
+
+impl Operation {
+    fn contains(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            Operation::Union => in_left || in_right,
+            Operation::Intersection => in_left && in_right,
+            Operation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Constructive solid geometry: solid made by combining the volumes of two shapes.
+///
+/// Both shapes must enclose a volume, see `Shape::intervals`: spheres, boxes, other `Csg`s
+/// or instances of them. Surfaces of the second shape cut out of the first one by a difference
+/// have their normals turned to point out of the result.
+pub struct Csg {
+    pub operation: Operation,
+    pub left: Box<dyn Shape>,
+    pub right: Box<dyn Shape>,
+}
+
+impl Csg {
+    pub fn new(operation: Operation, left: Box<dyn Shape>, right: Box<dyn Shape>) -> Csg {
+        Csg { operation, left, right }
+    }
+
+    pub fn union(left: Box<dyn Shape>, right: Box<dyn Shape>) -> Csg {
+        Csg::new(Operation::Union, left, right)
+    }
+
+    pub fn intersection(left: Box<dyn Shape>, right: Box<dyn Shape>) -> Csg {
+        Csg::new(Operation::Intersection, left, right)
+    }
+
+    /// `left` with `right` carved out of it.
+    pub fn difference(left: Box<dyn Shape>, right: Box<dyn Shape>) -> Csg {
+        Csg::new(Operation::Difference, left, right)
+    }
+}
+
+impl Shape for Csg {
+    fn translate(&mut self, d_pos: &Vec3) {
+        self.left.translate(d_pos);
+        self.right.translate(d_pos);
+    }
+
+    fn rotate_quat(&mut self, rotation: &Quat) {
+        // shapes turn about the center of their bounding box, move them back around a common pivot
+        let pivot = self.bounding_box().center();
+        let mat = rotation.to_mat3();
+
+        for shape in [&mut self.left, &mut self.right] {
+            let center = shape.bounding_box().center();
+            shape.rotate_quat(rotation);
+            shape.translate(&(mat * (center - pivot) + pivot - center));
+        }
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        // the ray may start inside the solid, in which case it is hit where it leaves it
+        self.intervals(ray).iter()
+            .flat_map(|interval| [interval.enter, interval.exit])
+            .find(|intersection| intersection.dist >= 0.0)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.operation {
+            Operation::Union => self.left.bounding_box().union(&self.right.bounding_box()),
+            Operation::Intersection | Operation::Difference => self.left.bounding_box(),
+        }
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        // boundaries of both shapes along the ray: the intersection, whether it belongs to
+        // the left shape and whether the ray enters the shape there
+        let boundaries = |shape: &dyn Shape, left: bool| {
+            shape.intervals(ray).into_iter()
+                .flat_map(move |interval| [(interval.enter, left, true), (interval.exit, left, false)])
+        };
+        let mut boundaries: Vec<_> = boundaries(self.left.as_ref(), true)
+            .chain(boundaries(self.right.as_ref(), false))
+            .collect();
+        boundaries.sort_by(|a, b| a.0.dist.partial_cmp(&b.0.dist).unwrap());
+
+        let mut depths = (0, 0);
+        let mut inside = false;
+        let mut enter = None;
+        let mut intervals = Vec::new();
+
+        for (intersection, left, entering) in boundaries {
+            let depth = if left { &mut depths.0 } else { &mut depths.1 };
+            *depth += if entering { 1 } else { -1 };
+
+            let now_inside = self.operation.contains(depths.0 > 0, depths.1 > 0);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+
+            // leaving the right shape of a difference enters the result: the normal is turned around
+            let intersection = if entering == now_inside { intersection } else { intersection.flipped() };
+
+            if now_inside {
+                enter = Some(intersection);
+            } else if let Some(enter) = enter.take() {
+                intervals.push(Interval { enter, exit: intersection });
+            }
+        }
+
+        intervals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::objects::{Diamond, Quad, Sphere};
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).norm() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn ball() -> Box<dyn Shape> {
+        Box::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0))
+    }
+
+    /// Box covering the x >= 0 half of the ball.
+    fn half_space() -> Box<dyn Shape> {
+        Box::new(Quad::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 4.0, 0.0), Vec3::new(0.0, 0.0, 4.0)))
+    }
+
+    #[test]
+    fn test_shape_intervals() {
+        // the whole line, behind the origin too
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.5), Vec3::new(0.0, 0.0, 1.0));
+        let intervals = ball().intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.dist + 1.5).abs() < 1e-5 && (intervals[0].exit.dist - 0.5).abs() < 1e-5);
+        assert_close(intervals[0].enter.normal, Vec3::new(0.0, 0.0, -1.0));
+        assert_close(intervals[0].exit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        let intervals = half_space().intervals(&Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.dist - 5.0).abs() < 1e-4 && (intervals[0].exit.dist - 7.0).abs() < 1e-4);
+        assert_close(intervals[0].enter.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert_close(intervals[0].exit.normal, Vec3::new(1.0, 0.0, 0.0));
+
+        assert!(Diamond::default().intervals(&ray).is_empty());
+    }
+
+    #[test]
+    fn test_difference() {
+        let half_ball = Csg::difference(ball(), half_space());
+
+        // the cut surface faces +x, where the box was
+        let hit = half_ball.intersect(&Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0))).unwrap();
+        assert!((hit.dist - 5.0).abs() < 1e-4, "got {:?}", hit);
+        assert_close(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+
+        let hit = half_ball.intersect(&Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))).unwrap();
+        assert!((hit.dist - 4.0).abs() < 1e-4);
+        assert_close(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+
+        // the removed half is empty
+        assert!(half_ball.intersect(&Ray::new(Vec3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0))).is_none());
+
+        // from inside, the ray leaves through the cut
+        let hit = half_ball.intersect(&Ray::new(Vec3::new(-0.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))).unwrap();
+        assert!((hit.dist - 0.5).abs() < 1e-4);
+        assert_close(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_rotated_difference() {
+        // a quarter turn about z brings the cut from x = 0 to y = 0, facing +y
+        let mut half_ball = Csg::difference(ball(), half_space());
+        half_ball.rotate_quat(&Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), std::f32::consts::PI / 2.0));
+
+        let hit = half_ball.intersect(&Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0))).unwrap();
+        assert!((hit.dist - 5.0).abs() < 1e-4, "got {:?}", hit);
+        assert_close(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+
+        let hit = half_ball.intersect(&Ray::new(Vec3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0))).unwrap();
+        assert!((hit.dist - 4.0).abs() < 1e-4);
+        assert!(half_ball.intersect(&Ray::new(Vec3::new(5.0, 0.5, 0.0), Vec3::new(-1.0, 0.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn test_union_and_intersection() {
+        let other: Box<dyn Shape> = Box::new(Sphere::new(Vec3::new(1.5, 0.0, 0.0), 1.0));
+        let along_x = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        // overlapping balls merge into a single interval
+        let union = Csg::union(ball(), other);
+        let intervals = union.intervals(&along_x);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.dist - 4.0).abs() < 1e-4 && (intervals[0].exit.dist - 7.5).abs() < 1e-4);
+
+        let other: Box<dyn Shape> = Box::new(Sphere::new(Vec3::new(1.5, 0.0, 0.0), 1.0));
+        let lens = Csg::intersection(ball(), other);
+        let hit = lens.intersect(&along_x).unwrap();
+        assert!((hit.dist - 5.5).abs() < 1e-4);
+        assert_close(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert!(lens.intersect(&Ray::new(Vec3::new(-0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0))).is_none());
+
+        // nested combinations: the lens, from x = 0.5 to 1, cut at x = 0.75
+        let mut cut = half_space();
+        cut.translate(&Vec3::new(0.75, 0.0, 0.0));
+        let cut_lens = Csg::difference(Box::new(lens), cut);
+        let intervals = cut_lens.intervals(&along_x);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.dist - 5.5).abs() < 1e-4 && (intervals[0].exit.dist - 5.75).abs() < 1e-4);
+        assert_close(intervals[0].exit.normal, Vec3::new(1.0, 0.0, 0.0));
+    }
+}
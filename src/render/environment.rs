@@ -0,0 +1,424 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::math::Vec3;
+
+use super::framebuffer::Framebuffer;
+use super::image::{self, Format};
+use super::light::Light;
+use super::objects::Color;
+use super::sampling::{self, Distribution};
+use super::tonemap::srgb_decode;
+
+/// Light coming from infinitely far away, seen along the rays leaving the scene.
+///
+/// It lights the scene like an area light surrounding it, in radiance (W/(sr·m²)).
+#[derive(Debug, Clone)]
+pub enum Environment {
+    /// Same radiance in every direction.
+    Constant(Color),
+    /// Sky fading from `horizon` up to `zenith`, above a uniform `ground`.
+    Gradient { zenith: Color, horizon: Color, ground: Color },
+    /// Clear sky lit by the sun, the sun itself being left to a directional light.
+    SunSky(SunSky),
+    /// Radiance read from an equirectangular image.
+    Map(Arc<EnvironmentMap>),
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::Constant(Color::black())
+    }
+}
+
+impl Environment {
+    pub fn gradient(zenith: Color, horizon: Color, ground: Color) -> Environment {
+        Environment::Gradient { zenith, horizon, ground }
+    }
+
+    /// Whether the environment gives off no light at all, lighting can then be skipped.
+    pub fn is_black(&self) -> bool {
+        matches!(self, Environment::Constant(color) if color.is_black())
+    }
+
+    /// Radiance arriving from `direction`.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Gradient { zenith, horizon, ground } => {
+                let y = direction.normalize().y;
+                if y < 0.0 {
+                    *ground
+                } else {
+                    *horizon * (1.0 - y) + *zenith * y
+                }
+            },
+            Environment::SunSky(sky) => sky.radiance(direction),
+            Environment::Map(map) => map.radiance(direction),
+        }
+    }
+
+    /// Direction to gather light from, for a surface of normal `normal`, along with its density.
+    ///
+    /// Maps are sampled according to their brightness, the other environments are smooth enough
+    /// for the directions to be spread around the normal (cosine-weighted).
+    pub fn sample(&self, normal: &Vec3, u1: f32, u2: f32) -> Option<(Vec3, f32)> {
+        match self {
+            Environment::Map(map) => map.sample(u1, u2),
+            _ => {
+                let direction = sampling::cosine_hemisphere(normal, u1, u2);
+                Some((direction, normal.dot(&direction).max(0.0) / PI))
+            },
+        }
+    }
+
+    /// Density of `sample` picking `direction` for a surface of normal `normal`.
+    pub fn pdf(&self, normal: &Vec3, direction: &Vec3) -> f32 {
+        match self {
+            Environment::Map(map) => map.pdf(direction),
+            _ => normal.dot(direction).max(0.0) / PI,
+        }
+    }
+}
+
+/// Analytic model of the clear sky of Preetham et al., "A Practical Analytic Model for Daylight".
+///
+/// The ground below the horizon is black, scenes usually have a floor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunSky {
+    /// Unit direction from the ground towards the sun.
+    sun_direction: Vec3,
+    turbidity: f32,
+    /// Coefficients of the Perez distribution of the luminance Y and of the chromaticities x and y.
+    perez: [[f32; 5]; 3],
+    /// Y (in kcd/m²), x and y at the zenith.
+    zenith: [f32; 3],
+}
+
+impl SunSky {
+    /// Sky for the sun in `sun_direction`, seen from the ground, through air of `turbidity`
+    /// from 2 (clear) to 10 (hazy).
+    pub fn new(sun_direction: Vec3, turbidity: f32) -> SunSky {
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity.clamp(1.7, 10.0);
+        let theta = sun_direction.y.clamp(0.0, 1.0).acos();
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        // polynomials in the turbidity and the angle of the sun
+        let chromaticity = |m: [[f32; 4]; 3]| {
+            let angles = [theta.powi(3), theta.powi(2), theta, 1.0];
+            let row = |r: [f32; 4]| r.iter().zip(angles).map(|(a, b)| a * b).sum::<f32>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        SunSky { sun_direction, turbidity: t, perez, zenith: [luminance.max(0.0), x, y] }
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    pub fn turbidity(&self) -> f32 {
+        self.turbidity
+    }
+
+    /// Directional light for the sun itself, of `irradiance` in W/m², tinted like the sky around it.
+    pub fn sun(&self, irradiance: f32) -> Light {
+        let [_, x, y] = self.sky_xyy(&self.sun_direction);
+        let color = xyy_to_rgb(1.0, x, y);
+        let max = color.rgb.x.max(color.rgb.y).max(color.rgb.z);
+
+        Light::directional(-self.sun_direction, color * (1.0 / max), irradiance)
+    }
+
+    /// Luminance and chromaticities of the sky in `direction`, above the horizon.
+    fn sky_xyy(&self, direction: &Vec3) -> [f32; 3] {
+        let cos_theta = direction.y.max(1e-3);
+        let cos_gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0);
+        let sun_theta = self.sun_direction.y.clamp(0.0, 1.0).acos();
+
+        let perez = |[a, b, c, d, e]: [f32; 5], cos_theta: f32, gamma: f32| {
+            (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+        };
+
+        std::array::from_fn(|i| {
+            self.zenith[i] * perez(self.perez[i], cos_theta, cos_gamma.acos()) / perez(self.perez[i], 1.0, sun_theta)
+        })
+    }
+
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let direction = direction.normalize();
+        if direction.y <= 0.0 {
+            return Color::black();
+        }
+
+        let [luminance, x, y] = self.sky_xyy(&direction);
+        // kcd/m² to W/(sr·m²), at the 683 lm/W of the peak sensitivity of the eye
+        xyy_to_rgb(luminance * 1000.0 / 683.0, x, y)
+    }
+}
+
+/// Linear sRGB colour of luminance `luminance` and chromaticities `x`, `y`.
+fn xyy_to_rgb(luminance: f32, x: f32, y: f32) -> Color {
+    if y <= 0.0 {
+        return Color::black();
+    }
+
+    let (cx, cy, cz) = (x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+    Color::new(
+        (3.2406 * cx - 1.5372 * cy - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * cy + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * cy + 1.0570 * cz).max(0.0),
+    )
+}
+
+/// Equirectangular image of the radiance around the scene, importance sampled for lighting.
+///
+/// The longitude goes along the width, the middle column looking towards +z,
+/// and the latitude down the height, the top row looking up.
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    /// Around the vertical axis, in radians.
+    rotation: f32,
+    /// Distribution of the rows, by brightness and solid angle.
+    rows: Distribution,
+    /// Distribution of the texels of each row, by brightness.
+    columns: Vec<Distribution>,
+}
+
+impl fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EnvironmentMap")
+            .field("width", &self.image.width)
+            .field("height", &self.image.height)
+            .field("rotation", &self.rotation.to_degrees())
+            .finish_non_exhaustive()
+    }
+}
+
+impl EnvironmentMap {
+    /// Map of the linear radiance of `image`.
+    pub fn new(image: Framebuffer) -> EnvironmentMap {
+        assert!(image.width > 0 && image.height > 0, "empty environment image");
+
+        let (width, height) = (image.width, image.height);
+        let columns: Vec<Distribution> = (0..height).map(|y| {
+            let weights: Vec<f32> = (0..width).map(|x| image.color(x, y).luminance()).collect();
+            Distribution::new(&weights)
+        }).collect();
+
+        // rows near the poles cover smaller solid angles
+        let rows: Vec<f32> = (0..height).map(|y| {
+            let sin_theta = ((y as f32 + 0.5) / height as f32 * PI).sin();
+            (0..width).map(|x| image.color(x, y).luminance()).sum::<f32>() * sin_theta
+        }).collect();
+
+        EnvironmentMap { rows: Distribution::new(&rows), columns, image, rotation: 0.0 }
+    }
+
+    /// Loads a HDR image, or a PPM or PNG one of sRGB encoded colours.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<EnvironmentMap> {
+        let mut image = image::load(&path)?;
+
+        if image.width == 0 || image.height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty environment image"));
+        }
+
+        if Format::from_path(&path) != Some(Format::Hdr) {
+            for pixel in &mut image.pixels {
+                for c in &mut pixel[..3] {
+                    *c = srgb_decode(*c);
+                }
+            }
+        }
+
+        Ok(EnvironmentMap::new(image))
+    }
+
+    /// Turns the map around the vertical axis by `degrees`.
+    pub fn with_rotation(self, degrees: f32) -> EnvironmentMap {
+        EnvironmentMap { rotation: degrees.to_radians(), ..self }
+    }
+
+    pub fn image(&self) -> &Framebuffer {
+        &self.image
+    }
+
+    /// Position of `direction` in the image, as fractions of its width and height.
+    fn uv(&self, direction: &Vec3) -> (f32, f32) {
+        let direction = direction.normalize();
+        let phi = direction.x.atan2(direction.z) - self.rotation;
+
+        ((0.5 + phi / (2.0 * PI)).rem_euclid(1.0), direction.y.clamp(-1.0, 1.0).acos() / PI)
+    }
+
+    fn direction(&self, u: f32, v: f32) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+
+        Vec3::new(theta.sin() * phi.sin(), theta.cos(), theta.sin() * phi.cos())
+    }
+
+    fn texel(&self, u: f32, v: f32) -> (u32, u32) {
+        let x = ((u * self.image.width as f32) as u32).min(self.image.width - 1);
+        let y = ((v * self.image.height as f32) as u32).min(self.image.height - 1);
+        (x, y)
+    }
+
+    /// Radiance of the texel in `direction`, unfiltered to match the sampling density.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.uv(direction);
+        let (x, y) = self.texel(u, v);
+        self.image.color(x, y)
+    }
+
+    /// Direction picked with a density proportional to the brightness of the map, and its density.
+    pub fn sample(&self, u1: f32, u2: f32) -> Option<(Vec3, f32)> {
+        let (v, row_pdf) = self.rows.sample(u2);
+        let (_, y) = self.texel(0.0, v);
+        let (u, column_pdf) = self.columns[y as usize].sample(u1);
+
+        // from the unit square of the image to the sphere of directions
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 || row_pdf * column_pdf <= 0.0 {
+            return None;
+        }
+
+        Some((self.direction(u, v), row_pdf * column_pdf / (2.0 * PI * PI * sin_theta)))
+    }
+
+    /// Density of `sample` picking `direction`.
+    pub fn pdf(&self, direction: &Vec3) -> f32 {
+        let (u, v) = self.uv(direction);
+        let (_, y) = self.texel(u, v);
+
+        // more precise than from v near the poles
+        let direction = direction.normalize();
+        let sin_theta = (direction.x * direction.x + direction.z * direction.z).sqrt();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        self.rows.pdf(v) * self.columns[y as usize].pdf(u) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    const UP: Vec3 = Vec3 { x: 0.0, y: 1.0, z: 0.0 };
+
+    #[test]
+    fn test_gradient() {
+        let sky = Environment::gradient(Color::new(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0), Color::black());
+
+        assert_eq!(sky.radiance(&UP), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(sky.radiance(&Vec3::new(1.0, 0.0, 0.0)), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(sky.radiance(&Vec3::new(1.0, -1.0, 0.0)), Color::black());
+        assert!(Environment::default().is_black() && !sky.is_black());
+    }
+
+    #[test]
+    fn test_sun_sky() {
+        let sky = SunSky::new(Vec3::new(0.0, 1.0, 1.0), 2.5);
+
+        let zenith = sky.radiance(&UP);
+        let near_sun = sky.radiance(&Vec3::new(0.0, 1.0, 1.2));
+        let away = sky.radiance(&Vec3::new(0.0, 1.0, -1.2));
+
+        // a few kcd/m², bluish, brightest around the sun
+        assert!(zenith.rgb.z > zenith.rgb.x && zenith.rgb.z > 1.0 && zenith.rgb.z < 50.0, "{:?}", zenith);
+        assert!(near_sun.luminance() > away.luminance());
+        assert!(sky.radiance(&-UP).is_black());
+
+        match sky.sun(1000.0) {
+            Light::Directional { direction, irradiance, .. } => {
+                assert!((direction + Vec3::new(0.0, 1.0, 1.0).normalize()).norm() < 1e-6);
+                assert_eq!(irradiance, 1000.0);
+            },
+            light => panic!("unexpected {:?}", light),
+        }
+    }
+
+    fn bright_spot_map() -> EnvironmentMap {
+        let mut image = Framebuffer::new(16, 8);
+        for x in 0..16 {
+            for y in 0..8 {
+                image.set_color(x, y, Color::new(0.1, 0.1, 0.1));
+            }
+        }
+        // just above the horizon, towards +z
+        image.set_color(8, 3, Color::new(100.0, 100.0, 100.0));
+
+        EnvironmentMap::new(image)
+    }
+
+    #[test]
+    fn test_map_directions() {
+        let map = bright_spot_map();
+        let towards_spot = Vec3::new(0.0, 0.2, 1.0);
+
+        assert_eq!(map.radiance(&towards_spot), Color::new(100.0, 100.0, 100.0));
+        assert_eq!(map.radiance(&Vec3::new(0.0, 0.2, -1.0)), Color::new(0.1, 0.1, 0.1));
+
+        // half a turn brings the spot behind
+        let turned = bright_spot_map().with_rotation(180.0);
+        assert_eq!(turned.radiance(&Vec3::new(0.0, 0.2, -1.0)), Color::new(100.0, 100.0, 100.0));
+
+        for (u, v) in [(0.3, 0.2), (0.8, 0.7)] {
+            let (u2, v2) = map.uv(&map.direction(u, v));
+            assert!((u - u2).abs() < 1e-5 && (v - v2).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_map_importance_sampling() {
+        let map = bright_spot_map();
+        let mut rng = rand::thread_rng();
+        let count = 20000;
+
+        let mut toward_spot = 0;
+        let mut integral = Color::black();
+
+        for _ in 0..count {
+            let (direction, pdf) = map.sample(rng.gen(), rng.gen()).unwrap();
+            assert!((direction.norm() - 1.0).abs() < 1e-5);
+            assert!((map.pdf(&direction) - pdf).abs() < 1e-3 * pdf, "{} != {}", map.pdf(&direction), pdf);
+
+            toward_spot += (map.radiance(&direction).rgb.x > 1.0) as u32;
+            integral += map.radiance(&direction) * (1.0 / (pdf * count as f32));
+        }
+
+        // most samples go towards the spot, which holds most of the light
+        assert!(toward_spot > count / 2, "{} samples towards the spot", toward_spot);
+
+        // the spot covers (2 PI / 16) * (cos(3 PI / 8) - cos(PI / 2)) steradians
+        let spot = 100.0 * 2.0 * PI / 16.0 * (3.0 * PI / 8.0).cos();
+        let expected = spot + 0.1 * (4.0 * PI - 2.0 * PI / 16.0 * (3.0 * PI / 8.0).cos());
+        assert!((integral.rgb.x - expected).abs() < 0.02 * expected, "{} != {}", integral.rgb.x, expected);
+    }
+}
@@ -0,0 +1,110 @@
+/// Pixel reconstruction filter: weights the samples falling around a pixel by their
+/// distance to its center, so a sample may count for several neighbouring pixels.
+///
+/// Filters are separable, the weight is the product of the weights along x and y.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Filter {
+    /// Each pixel is the plain average of the samples inside it.
+    #[default]
+    Box,
+    /// Weight decreasing linearly to zero at `radius` pixels, at least half a pixel.
+    Tent { radius: f32 },
+    /// Gaussian of standard deviation `sigma` pixels, cut off at three times `sigma`.
+    /// `sigma` is at least a sixth of a pixel.
+    Gaussian { sigma: f32 },
+    /// Mitchell-Netravali cubic over two pixels; `b = c = 1 / 3` is the usual compromise
+    /// between blurring and ringing.
+    Mitchell { b: f32, c: f32 },
+}
+
+/// Narrower filters would give no weight to the samples in between pixel centers.
+const MIN_RADIUS: f32 = 0.5;
+
+impl Filter {
+    pub fn mitchell() -> Filter {
+        Filter::Mitchell { b: 1.0 / 3.0, c: 1.0 / 3.0 }
+    }
+
+    /// Distance from the pixel center, in pixels, past which samples have no weight.
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box => 0.5,
+            Filter::Tent { radius } => radius.max(MIN_RADIUS),
+            Filter::Gaussian { sigma } => (3.0 * sigma).max(MIN_RADIUS),
+            Filter::Mitchell { .. } => 2.0,
+        }
+    }
+
+    /// Weight of a sample at (dx, dy) pixels from the center of a pixel.
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, d: f32) -> f32 {
+        match *self {
+            // half open so that a sample on the border of two pixels is only counted once
+            Filter::Box => if (-0.5..0.5).contains(&d) { 1.0 } else { 0.0 },
+            Filter::Tent { .. } => (1.0 - d.abs() / self.radius()).max(0.0),
+            Filter::Gaussian { .. } => {
+                let sigma = self.radius() / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                // shifted so that it reaches zero at the radius instead of being cut abruptly
+                (gaussian(d) - gaussian(self.radius())).max(0.0)
+            },
+            Filter::Mitchell { b, c } => {
+                let x = d.abs();
+                let weight = if x < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+                } else if x < 2.0 {
+                    (-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+                } else {
+                    0.0
+                };
+                weight / 6.0
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_filter() {
+        assert_eq!(Filter::Box.weight(0.0, 0.0), 1.0);
+        assert_eq!(Filter::Box.weight(-0.5, 0.49), 1.0);
+        assert_eq!(Filter::Box.weight(0.5, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_filters_vanish_at_their_radius() {
+        for filter in [Filter::Tent { radius: 1.5 }, Filter::Gaussian { sigma: 0.5 }, Filter::mitchell()] {
+            let radius = filter.radius();
+            assert!(filter.weight(0.0, 0.0) > 0.0, "{:?}", filter);
+            assert!(filter.weight(0.0, 0.0) > filter.weight(0.5, 0.0), "{:?}", filter);
+            assert!(filter.weight(radius, 0.0).abs() < 1e-6, "{:?}", filter);
+            assert_eq!(filter.weight(0.0, radius + 0.1), 0.0, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_degenerate_filters() {
+        // clamped to half a pixel instead of dividing by zero
+        for filter in [Filter::Tent { radius: 0.0 }, Filter::Gaussian { sigma: 0.0 }] {
+            assert_eq!(filter.radius(), 0.5, "{:?}", filter);
+            assert!(filter.weight(0.0, 0.0) > 0.0 && filter.weight(0.4, -0.4) > 0.0, "{:?}", filter);
+            assert_eq!(filter.weight(0.5, 0.0), 0.0, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_mitchell_partition_of_unity() {
+        // a flat signal sampled at every pixel is reconstructed exactly
+        let filter = Filter::mitchell();
+        for offset in [0.0, 0.25, 0.5, 0.8] {
+            let sum: f32 = (-2..=2).map(|k| filter.weight_1d(offset + k as f32)).sum();
+            assert!((sum - 1.0).abs() < 1e-5, "offset {}: {}", offset, sum);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use super::objects::Color;
+use super::tonemap::ToneMapping;
+
+/// Something a rendered frame can be presented on: a window, an image file, a test...
+pub trait RenderTarget {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    fn draw(&mut self, framebuffer: &Framebuffer);
+}
+
+/// In-memory RGBA float image, stored row by row starting from the top left corner.
+///
+/// The alpha channel tells whether a ray hit something for the pixel (1.0)
+/// or went straight through the scene (0.0).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel ({}, {}) out of bounds", x, y);
+        (y * self.width + x) as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> [f32; 4] {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, pixel: [f32; 4]) {
+        let index = self.index(x, y);
+        self.pixels[index] = pixel;
+    }
+
+    pub fn color(&self, x: u32, y: u32) -> Color {
+        let [r, g, b, _] = self.get(x, y);
+        Color::new(r, g, b)
+    }
+
+    pub fn alpha(&self, x: u32, y: u32) -> f32 {
+        self.get(x, y)[3]
+    }
+
+    pub fn set_color(&mut self, x: u32, y: u32, color: Color) {
+        self.set(x, y, [color.rgb.x, color.rgb.y, color.rgb.z, 1.0]);
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.fill([0.0; 4]);
+    }
+
+    /// Packs the image into 8 bits per channel sRGB, composited over black.
+    pub fn to_rgb8(&self, tone_mapping: &ToneMapping) -> Vec<u8> {
+        self.pixels.iter().flat_map(|[r, g, b, a]| {
+            tone_mapping.to_srgb8(Color::new(r * a, g * a, b * a))
+        }).collect()
+    }
+}
+
+impl RenderTarget for Framebuffer {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn draw(&mut self, framebuffer: &Framebuffer) {
+        self.clone_from(framebuffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_framebuffer_is_transparent() {
+        let fb = Framebuffer::new(4, 3);
+        assert_eq!(fb.pixels.len(), 12);
+        assert!(fb.pixels.iter().all(|p| *p == [0.0; 4]));
+    }
+
+    #[test]
+    fn test_set_and_get_pixels() {
+        let mut fb = Framebuffer::new(4, 3);
+        fb.set_color(3, 2, Color::new(0.25, 0.5, 1.0));
+        assert_eq!(fb.get(3, 2), [0.25, 0.5, 1.0, 1.0]);
+        assert_eq!(fb.pixels[11], [0.25, 0.5, 1.0, 1.0]);
+        assert_eq!(fb.alpha(0, 0), 0.0);
+    }
+
+    #[test]
+    fn test_to_rgb8_saturates() {
+        let mut fb = Framebuffer::new(2, 1);
+        fb.set_color(0, 0, Color::new(2.0, 0.5, -1.0));
+        // 0.5 is gamma encoded
+        assert_eq!(fb.to_rgb8(&ToneMapping::default()), vec![255, 188, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_framebuffer_as_target() {
+        let mut source = Framebuffer::new(2, 2);
+        source.set_color(1, 1, Color::new(1.0, 1.0, 1.0));
+        let mut target = Framebuffer::new(2, 2);
+        target.draw(&source);
+        assert_eq!(target, source);
+    }
+}
@@ -0,0 +1,511 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::framebuffer::Framebuffer;
+use super::tonemap::ToneMapping;
+
+/// Binary (P6) PPM, 8 bits per channel sRGB.
+pub fn write_ppm<W: Write>(framebuffer: &Framebuffer, tone_mapping: &ToneMapping, mut writer: W) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", framebuffer.width, framebuffer.height)?;
+    writer.write_all(&framebuffer.to_rgb8(tone_mapping))
+}
+
+/// 8 bits per channel sRGB PNG.
+pub fn write_png<W: Write>(framebuffer: &Framebuffer, tone_mapping: &ToneMapping, writer: W) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, framebuffer.width, framebuffer.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&framebuffer.to_rgb8(tone_mapping))?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Little endian color PFM, keeps the raw float values of the framebuffer.
+pub fn write_pfm<W: Write>(framebuffer: &Framebuffer, mut writer: W) -> io::Result<()> {
+    write!(writer, "PF\n{} {}\n-1.0\n", framebuffer.width, framebuffer.height)?;
+
+    // PFM scanlines go from the bottom of the image to the top
+    for row in framebuffer.pixels.chunks(framebuffer.width as usize).rev() {
+        for [r, g, b, a] in row {
+            for c in [r * a, g * a, b * a] {
+                writer.write_all(&c.to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Radiance HDR (RGBE) with flat, uncompressed scanlines.
+pub fn write_hdr<W: Write>(framebuffer: &Framebuffer, mut writer: W) -> io::Result<()> {
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        framebuffer.height,
+        framebuffer.width,
+    )?;
+
+    for [r, g, b, a] in &framebuffer.pixels {
+        writer.write_all(&to_rgbe(r * a, g * a, b * a))?;
+    }
+
+    Ok(())
+}
+
+fn to_rgbe(r: f32, g: f32, b: f32) -> [u8; 4] {
+    let v = r.max(g).max(b);
+
+    if v < 1e-32 {
+        return [0; 4];
+    }
+
+    // v = m * 2^e with m in [0.5, 1)
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f32.powi(e);
+
+    [
+        (r.max(0.0) * scale) as u8,
+        (g.max(0.0) * scale) as u8,
+        (b.max(0.0) * scale) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Ppm,
+    Png,
+    Pfm,
+    Hdr,
+}
+
+impl Format {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "ppm" => Some(Format::Ppm),
+            "png" => Some(Format::Png),
+            "pfm" => Some(Format::Pfm),
+            "hdr" => Some(Format::Hdr),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Next whitespace separated token of a PPM header, skipping `#` comments.
+fn ppm_token<'a>(bytes: &'a [u8], position: &mut usize) -> io::Result<&'a [u8]> {
+    loop {
+        match bytes.get(*position) {
+            Some(b'#') => {
+                while bytes.get(*position).is_some_and(|&b| b != b'\n') {
+                    *position += 1;
+                }
+            },
+            Some(b) if b.is_ascii_whitespace() => *position += 1,
+            Some(_) => break,
+            None => return Err(invalid_data("truncated PPM")),
+        }
+    }
+
+    let start = *position;
+    while bytes.get(*position).is_some_and(|b| !b.is_ascii_whitespace()) {
+        *position += 1;
+    }
+
+    Ok(&bytes[start..*position])
+}
+
+fn ppm_number(bytes: &[u8], position: &mut usize) -> io::Result<u32> {
+    let token = ppm_token(bytes, position)?;
+
+    std::str::from_utf8(token).ok()
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| invalid_data("invalid number in PPM"))
+}
+
+/// ASCII (P3) or binary (P6) PPM, of up to 16 bits per channel.
+pub fn read_ppm<R: Read>(mut reader: R) -> io::Result<Framebuffer> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut position = 0;
+    let magic = ppm_token(&bytes, &mut position)?;
+    let width = ppm_number(&bytes, &mut position)?;
+    let height = ppm_number(&bytes, &mut position)?;
+    let max = ppm_number(&bytes, &mut position)?;
+
+    if max == 0 || max > 65535 {
+        return Err(invalid_data("invalid PPM maximum value"));
+    }
+
+    let count = 3 * width as usize * height as usize;
+
+    let samples: Vec<u32> = match magic {
+        b"P3" => (0..count).map(|_| ppm_number(&bytes, &mut position)).collect::<io::Result<_>>()?,
+        b"P6" => {
+            // a single whitespace character separates the header from the samples
+            let data = bytes.get(position + 1..).unwrap_or(&[]);
+            let size = if max < 256 { 1 } else { 2 };
+
+            if data.len() < count * size {
+                return Err(invalid_data("truncated PPM"));
+            }
+
+            // big endian when on two bytes
+            data.chunks(size).take(count)
+                .map(|sample| sample.iter().fold(0, |value, &b| value << 8 | b as u32))
+                .collect()
+        },
+        _ => return Err(invalid_data("not a P3 or P6 PPM")),
+    };
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    let max = max as f32;
+
+    for (pixel, rgb) in framebuffer.pixels.iter_mut().zip(samples.chunks(3)) {
+        *pixel = [rgb[0] as f32 / max, rgb[1] as f32 / max, rgb[2] as f32 / max, 1.0];
+    }
+
+    Ok(framebuffer)
+}
+
+/// PNG of any colour type, reduced to 8 bits per channel.
+pub fn read_png<R: Read>(reader: R) -> io::Result<Framebuffer> {
+    let mut decoder = png::Decoder::new(reader);
+    // palettes are expanded and 16 bits channels stripped
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+
+    let channels = info.color_type.samples();
+    let mut framebuffer = Framebuffer::new(info.width, info.height);
+    let to_float = |c: u8| c as f32 / 255.0;
+
+    let rows = buffer.chunks(info.line_size).take(info.height as usize);
+    let pixels = rows.flat_map(|row| row[..channels * info.width as usize].chunks(channels));
+
+    for (pixel, samples) in framebuffer.pixels.iter_mut().zip(pixels) {
+        let samples: Vec<f32> = samples.iter().map(|&c| to_float(c)).collect();
+
+        *pixel = match samples[..] {
+            [l] => [l, l, l, 1.0],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 1.0],
+            [r, g, b, a] => [r, g, b, a],
+            _ => return Err(invalid_data("unexpected PNG colour type")),
+        };
+    }
+
+    Ok(framebuffer)
+}
+
+/// Radiance HDR (RGBE), flat or run-length encoded, of the usual `-Y height +X width` orientation.
+pub fn read_hdr<R: Read>(mut reader: R) -> io::Result<Framebuffer> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut position = 0;
+    let next_line = |position: &mut usize| -> io::Result<String> {
+        let end = bytes[*position..].iter().position(|&b| b == b'\n').ok_or_else(|| invalid_data("truncated HDR header"))?;
+        let line = String::from_utf8_lossy(&bytes[*position..*position + end]).into_owned();
+        *position += end + 1;
+        Ok(line)
+    };
+
+    let magic = next_line(&mut position)?;
+    if !magic.starts_with("#?") {
+        return Err(invalid_data("not a Radiance HDR"));
+    }
+
+    // variables up to an empty line, then the resolution
+    loop {
+        let line = next_line(&mut position)?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data("unsupported HDR format"));
+        }
+    }
+
+    let resolution = next_line(&mut position)?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse().ok(), width.parse().ok()),
+        _ => return Err(invalid_data("unsupported HDR orientation")),
+    };
+    let (height, width): (u32, u32) = height.zip(width).ok_or_else(|| invalid_data("invalid HDR resolution"))?;
+    if width == 0 || height == 0 {
+        return Err(invalid_data("empty HDR image"));
+    }
+
+    // smallest possible scanline: a run of 127 bytes takes 2 bytes per channel, check before allocating
+    let rle = (8..0x8000).contains(&width);
+    let min_scanline = if rle { 4 + 8 * width.div_ceil(127) as u64 } else { 4 * width as u64 };
+    if height as u64 * min_scanline > (bytes.len() - position) as u64 {
+        return Err(invalid_data("truncated HDR"));
+    }
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    let mut data = bytes[position..].iter().copied();
+    let mut next = || data.next().ok_or_else(|| invalid_data("truncated HDR"));
+    let mut scanline = vec![[0u8; 4]; width as usize];
+
+    for row in framebuffer.pixels.chunks_mut(width as usize) {
+        let start = [next()?, next()?, next()?, next()?];
+
+        if rle && start[..2] == [2, 2] && start[2] & 0x80 == 0 {
+            if ((start[2] as usize) << 8 | start[3] as usize) != scanline.len() {
+                return Err(invalid_data("invalid HDR scanline width"));
+            }
+
+            // each channel in turn, as runs of a repeated byte or of literal ones
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < scanline.len() {
+                    let count = next()? as usize;
+                    let (count, run) = if count > 128 { (count - 128, Some(next()?)) } else { (count, None) };
+
+                    if count == 0 || x + count > scanline.len() {
+                        return Err(invalid_data("invalid HDR run length"));
+                    }
+                    for rgbe in &mut scanline[x..x + count] {
+                        rgbe[channel] = match run {
+                            Some(value) => value,
+                            None => next()?,
+                        };
+                    }
+                    x += count;
+                }
+            }
+        } else {
+            scanline[0] = start;
+            for rgbe in &mut scanline[1..] {
+                *rgbe = [next()?, next()?, next()?, next()?];
+            }
+        }
+
+        for (pixel, &rgbe) in row.iter_mut().zip(&scanline) {
+            let [r, g, b] = from_rgbe(rgbe);
+            *pixel = [r, g, b, 1.0];
+        }
+    }
+
+    Ok(framebuffer)
+}
+
+fn from_rgbe([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+
+    let scale = 2f32.powi(e as i32 - 136);
+    [r as f32 * scale, g as f32 * scale, b as f32 * scale]
+}
+
+/// Writes the framebuffer in `format`. The tone mapping only applies to the 8 bits formats,
+/// PFM and HDR keep the linear values.
+pub fn write<W: Write>(framebuffer: &Framebuffer, format: Format, tone_mapping: &ToneMapping, writer: W) -> io::Result<()> {
+    match format {
+        Format::Ppm => write_ppm(framebuffer, tone_mapping, writer),
+        Format::Png => write_png(framebuffer, tone_mapping, writer),
+        Format::Pfm => write_pfm(framebuffer, writer),
+        Format::Hdr => write_hdr(framebuffer, writer),
+    }
+}
+
+/// Writes the framebuffer to `path`, the format is picked from the file extension.
+pub fn save<P: AsRef<Path>>(framebuffer: &Framebuffer, path: P, tone_mapping: &ToneMapping) -> io::Result<()> {
+    let path = path.as_ref();
+
+    let format = Format::from_path(path).ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported image format: {}", path.display()),
+    ))?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(framebuffer, format, tone_mapping, &mut writer)?;
+    writer.flush()
+}
+
+/// Reads the image at `path`, the format is picked from the file extension.
+/// PPM and PNG values are returned as stored, sRGB encoded colours usually, HDR ones are linear.
+/// PFM can't be read.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Framebuffer> {
+    let path = path.as_ref();
+
+    match Format::from_path(path) {
+        Some(Format::Ppm) => read_ppm(BufReader::new(File::open(path)?)),
+        Some(Format::Png) => read_png(BufReader::new(File::open(path)?)),
+        Some(Format::Hdr) => read_hdr(BufReader::new(File::open(path)?)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported image format: {}", path.display()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::objects::Color;
+
+    fn test_framebuffer() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.set_color(0, 0, Color::new(1.0, 0.0, 0.0));
+        framebuffer.set_color(1, 1, Color::new(0.0, 0.0, 2.0));
+        framebuffer
+    }
+
+    #[test]
+    fn test_write_ppm() {
+        let mut bytes = Vec::new();
+        write_ppm(&test_framebuffer(), &ToneMapping::default(), &mut bytes).unwrap();
+
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(&bytes[header.len()..], &[255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_read_ppm() {
+        let mut bytes = Vec::new();
+        write_ppm(&test_framebuffer(), &ToneMapping::default(), &mut bytes).unwrap();
+        let read = read_ppm(&bytes[..]).unwrap();
+        assert_eq!(read.color(0, 0), Color::new(1.0, 0.0, 0.0));
+        // clamped when written
+        assert_eq!(read.color(1, 1), Color::new(0.0, 0.0, 1.0));
+
+        let ascii = b"P3\n# two pixels\n2 1\n4\n4 0 0  0 2 4\n";
+        let read = read_ppm(&ascii[..]).unwrap();
+        assert_eq!(read.color(0, 0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(read.color(1, 0), Color::new(0.0, 0.5, 1.0));
+
+        let error = read_ppm(&b"P6\n2 2\n255\n\x00\x01"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let mut bytes = Vec::new();
+        write_png(&test_framebuffer(), &ToneMapping::default(), &mut bytes).unwrap();
+        let read = read_png(&bytes[..]).unwrap();
+
+        assert_eq!((read.width, read.height), (2, 2));
+        assert_eq!(read.color(0, 0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(read.color(1, 1), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(read.alpha(1, 0), 1.0);
+    }
+
+    #[test]
+    fn test_write_png_signature() {
+        let mut bytes = Vec::new();
+        write_png(&test_framebuffer(), &ToneMapping::default(), &mut bytes).unwrap();
+        assert_eq!(&bytes[..8], &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+    }
+
+    #[test]
+    fn test_write_pfm_bottom_up() {
+        let mut bytes = Vec::new();
+        write_pfm(&test_framebuffer(), &mut bytes).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 2 * 2 * 3 * 4);
+
+        let floats: Vec<f32> = bytes[header.len()..].chunks(4)
+            .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+
+        // bottom row first: black, then blue 2.0
+        assert_eq!(&floats[..6], &[0.0, 0.0, 0.0, 0.0, 0.0, 2.0]);
+        // top row last: red, then black
+        assert_eq!(&floats[6..], &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_rgbe() {
+        assert_eq!(to_rgbe(0.0, 0.0, 0.0), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(1.0, 0.5, 0.0), [128, 64, 0, 129]);
+        assert_eq!(to_rgbe(0.0, 0.0, 2.0), [0, 0, 128, 130]);
+    }
+
+    #[test]
+    fn test_hdr_round_trip() {
+        let mut bytes = Vec::new();
+        write_hdr(&test_framebuffer(), &mut bytes).unwrap();
+        let read = read_hdr(&bytes[..]).unwrap();
+
+        assert_eq!((read.width, read.height), (2, 2));
+        assert_eq!(read.color(0, 0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(read.color(1, 1), Color::new(0.0, 0.0, 2.0));
+        assert_eq!(read.color(1, 0), Color::black());
+    }
+
+    #[test]
+    fn test_read_run_length_encoded_hdr() {
+        let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        bytes.extend([2, 2, 0, 8]);
+        // red: a run of 8, green: 8 literals, blue: two runs of 4, exponent: a run of 8
+        bytes.extend([136, 128]);
+        bytes.extend([8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        bytes.extend([132, 0, 132, 64]);
+        bytes.extend([136, 129]);
+
+        let read = read_hdr(&bytes[..]).unwrap();
+        assert_eq!((read.width, read.height), (8, 1));
+        assert_eq!(read.color(0, 0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(read.color(7, 0), Color::new(1.0, 0.875, 0.5));
+
+        let error = read_hdr(&b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_read_mismatched_hdr_scanline() {
+        // the scanline claims 9 pixels in an image 8 pixels wide
+        let mut bytes = b"#?RADIANCE\n\n-Y 1 +X 8\n".to_vec();
+        bytes.extend([2, 2, 0, 9]);
+        bytes.extend([136, 128, 136, 0, 136, 0, 136, 129]);
+
+        let error = read_hdr(&bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "invalid HDR scanline width");
+    }
+
+    #[test]
+    fn test_read_invalid_hdr_size() {
+        // empty, then far more pixels than the data could hold, without allocating them
+        for header in [&b"#?RADIANCE\n\n-Y 4 +X 0\n"[..], b"#?RADIANCE\n\n-Y 100000 +X 100000\n\x02\x02\x00\x08"] {
+            let error = read_hdr(header).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("out/frame.PNG"), Some(Format::Png));
+        assert_eq!(Format::from_path("frame.hdr"), Some(Format::Hdr));
+        assert_eq!(Format::from_path("frame"), None);
+    }
+
+    #[test]
+    fn test_save_unknown_format() {
+        let error = save(&test_framebuffer(), "frame.bmp", &ToneMapping::default()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let error = load("frame.pfm").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use crate::math::{Mat3, Quat, Transform, Vec3};
+
+use super::bvh::Aabb;
+use super::objects::{Intersection, Interval, Ray, Shape};
+
+/// A shape placed in the scene by a transform, without copying it: the same mesh
+/// can be shared by many instances, each one scaled, rotated and moved differently.
+///
+/// Rays are brought into the space of the shape to be intersected,
+/// and the intersection is brought back into world space.
+///
+/// An animated instance moves from one transform to another over a time interval,
+/// rays see it where it is at their own time, which blurs it in motion.
+#[derive(Clone)]
+pub struct Instance {
+    shape: Arc<dyn Shape>,
+    transform: Transform,
+    motion: Option<Motion>,
+    bounds: Aabb,
+}
+
+/// Where an animated instance ends up, and when it leaves `Instance::transform` and reaches `end`.
+#[derive(Debug, Clone, Copy)]
+struct Motion {
+    end: Transform,
+    start_time: f32,
+    end_time: f32,
+    /// Both transforms decomposed once and for all, to interpolate them for every ray.
+    start_parts: (Vec3, Quat, Mat3),
+    end_parts: (Vec3, Quat, Mat3),
+}
+
+impl Motion {
+    fn new(start: &Transform, end: Transform, start_time: f32, end_time: f32) -> Motion {
+        Motion {
+            end,
+            start_time,
+            end_time,
+            start_parts: start.decompose(),
+            end_parts: end.decompose(),
+        }
+    }
+}
+
+/// Number of instants at which the bounds of an animated instance are computed.
+const MOTION_BOUNDS_STEPS: usize = 32;
+
+impl Instance {
+    /// `transform` maps the space of `shape` to world space.
+    pub fn new(shape: Arc<dyn Shape>, transform: Transform) -> Instance {
+        let bounds = transform_bounds(&shape.bounding_box(), &transform);
+        Instance { shape, transform, motion: None, bounds }
+    }
+
+    /// Instance moving from `start` at `start_time` to `end` at `end_time`,
+    /// staying still before and after.
+    pub fn animated(shape: Arc<dyn Shape>, start: Transform, start_time: f32, end: Transform, end_time: f32) -> Instance {
+        let mut instance = Instance::new(shape, start);
+        instance.motion = Some(Motion::new(&start, end, start_time, end_time));
+        instance.update_bounds();
+        instance
+    }
+
+    pub fn shape(&self) -> &Arc<dyn Shape> {
+        &self.shape
+    }
+
+    /// Transform of the instance, at the start of its motion if it is animated.
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    /// Places the instance, which stops being animated.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.motion = None;
+        self.update_bounds();
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.motion.is_some()
+    }
+
+    pub fn transform_at(&self, time: f32) -> Transform {
+        match &self.motion {
+            None => self.transform,
+            Some(motion) => {
+                let duration = motion.end_time - motion.start_time;
+                let t = if duration > 0.0 { ((time - motion.start_time) / duration).clamp(0.0, 1.0) } else { 1.0 };
+
+                let (translation_a, rotation_a, scale_a) = &motion.start_parts;
+                let (translation_b, rotation_b, scale_b) = &motion.end_parts;
+                Transform::from_parts(
+                    &((1.0 - t) * *translation_a + t * *translation_b),
+                    &rotation_a.slerp(rotation_b, t),
+                    &((1.0 - t) * *scale_a + t * *scale_b),
+                ).unwrap_or(self.transform)
+            },
+        }
+    }
+
+    /// Applies `transform` after the transforms of the instance, at every step of its motion.
+    fn apply(&mut self, transform: &Transform) {
+        self.transform = self.transform.then(transform);
+        if let Some(motion) = self.motion {
+            self.motion = Some(Motion::new(&self.transform, motion.end.then(transform), motion.start_time, motion.end_time));
+        }
+        self.update_bounds();
+    }
+
+    fn update_bounds(&mut self) {
+        let shape_bounds = self.shape.bounding_box();
+
+        self.bounds = match &self.motion {
+            None => transform_bounds(&shape_bounds, &self.transform),
+            // sampled along the way: rotations may sweep slightly out of the box in between
+            Some(motion) => (0..=MOTION_BOUNDS_STEPS).fold(Aabb::empty(), |bounds, step| {
+                let t = step as f32 / MOTION_BOUNDS_STEPS as f32;
+                let time = motion.start_time + t * (motion.end_time - motion.start_time);
+                bounds.union(&transform_bounds(&shape_bounds, &self.transform_at(time)))
+            }),
+        };
+    }
+
+    /// Scales the instance around its center.
+    pub fn scale(&mut self, factors: &Vec3) {
+        self.transform_around_center(&Transform::scale(factors));
+    }
+
+    fn transform_around_center(&mut self, transform: &Transform) {
+        let center = self.bounds.center();
+        let around_center = Transform::translation(&-center)
+            .then(transform)
+            .then(&Transform::translation(&center));
+
+        self.apply(&around_center);
+    }
+
+    /// Transform of the instance at the time of `ray`, and `ray` in object space along with
+    /// the factor distances along it are scaled by, as it is normalized again.
+    fn local_ray(&self, ray: &Ray) -> (Transform, Ray, f32) {
+        let transform = self.transform_at(ray.time);
+        let inverse = transform.inverse();
+        let direction = inverse.direction(&ray.direction);
+
+        let local_ray = Ray::new(inverse.point(&ray.origin), direction).at_time(ray.time);
+        (transform, local_ray, direction.norm())
+    }
+}
+
+/// Object space intersection `local` of the local ray of `ray`, back in world space.
+fn to_world(transform: &Transform, ray: &Ray, scale: f32, local: &Intersection) -> Intersection {
+    let dist = local.dist / scale;
+
+    Intersection {
+        point: ray.origin + dist * ray.direction,
+        dist,
+        normal: transform.normal(&local.normal).normalize(),
+        uv: local.uv,
+        tangent: transform.direction(&local.tangent).normalize(),
+    }
+}
+
+/// Box bounding the transformed corners of `bounds`.
+fn transform_bounds(bounds: &Aabb, transform: &Transform) -> Aabb {
+    if bounds.is_empty() {
+        return *bounds;
+    }
+
+    let mut corners = Vec::with_capacity(8);
+    for x in [bounds.min.x, bounds.max.x] {
+        for y in [bounds.min.y, bounds.max.y] {
+            for z in [bounds.min.z, bounds.max.z] {
+                corners.push(transform.point(&Vec3::new(x, y, z)));
+            }
+        }
+    }
+
+    Aabb::from_points(&corners)
+}
+
+impl Shape for Instance {
+    fn translate(&mut self, d_pos: &Vec3) {
+        self.apply(&Transform::translation(d_pos));
+    }
+
+    fn rotate_quat(&mut self, rotation: &Quat) {
+        self.transform_around_center(&Transform::rotation_quat(rotation));
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (transform, local_ray, scale) = self.local_ray(ray);
+        let local = self.shape.intersect(&local_ray)?;
+
+        Some(to_world(&transform, ray, scale, &local))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let (transform, local_ray, scale) = self.local_ray(ray);
+
+        self.shape.intervals(&local_ray).iter().map(|interval| Interval {
+            enter: to_world(&transform, ray, scale, &interval.enter),
+            exit: to_world(&transform, ray, scale, &interval.exit),
+        }).collect()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::mesh::TriangleMesh;
+    use crate::render::objects::{Quad, Sphere};
+
+    #[test]
+    fn test_scaled_and_translated_sphere() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0));
+        let transform = Transform::scale(&Vec3::new(2.0, 1.0, 1.0)).then(&Transform::translation(&Vec3::new(0.0, 0.0, 10.0)));
+        let instance = Instance::new(sphere, transform);
+
+        let hit = instance.intersect(&Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0))).unwrap();
+        assert!((hit.dist - 9.0).abs() < 1e-5);
+        assert!((hit.point - Vec3::new(0.0, 0.0, 9.0)).norm() < 1e-5);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, -1.0)).norm() < 1e-5);
+
+        // stretched along x: the ellipsoid is hit from the side two units away from its center
+        let side = instance.intersect(&Ray::new(Vec3::new(-10.0, 0.0, 10.0), Vec3::new(1.0, 0.0, 0.0))).unwrap();
+        assert!((side.dist - 8.0).abs() < 1e-5);
+
+        // gradient of the ellipsoid x² / 4 + y² + z² = 1 at (1, 0, sqrt(3) / 2): (1 / 2, 0, sqrt(3))
+        let oblique = instance.intersect(&Ray::new(Vec3::new(1.0, 0.0, 20.0), Vec3::new(0.0, 0.0, -1.0))).unwrap();
+        let expected = Vec3::new(0.5, 0.0, 3.0_f32.sqrt()).normalize();
+        assert!((oblique.normal - expected).norm() < 1e-5, "got {:?}", oblique.normal);
+
+        let bounds = instance.bounding_box();
+        assert!((bounds.min - Vec3::new(-2.0, -1.0, 9.0)).norm() < 1e-5);
+        assert!((bounds.max - Vec3::new(2.0, 1.0, 11.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_intervals_are_transformed() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0));
+        let transform = Transform::scale(&Vec3::new(1.0, 1.0, 2.0)).then(&Transform::translation(&Vec3::new(0.0, 0.0, 10.0)));
+        let instance = Instance::new(sphere, transform);
+
+        let intervals = instance.intervals(&Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)));
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.dist - 8.0).abs() < 1e-5 && (intervals[0].exit.dist - 12.0).abs() < 1e-5);
+        assert!((intervals[0].exit.normal - Vec3::new(0.0, 0.0, 1.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_instances_share_their_mesh() {
+        let vertices = vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        let mesh: Arc<dyn Shape> = Arc::new(TriangleMesh::new(vertices, vec![[0, 1, 2]]));
+
+        let instances: Vec<Instance> = (0..10).map(|i| {
+            Instance::new(mesh.clone(), Transform::translation(&Vec3::new(10.0 * i as f32, 0.0, 5.0)))
+        }).collect();
+
+        for (i, instance) in instances.iter().enumerate() {
+            let ray = Ray::new(Vec3::new(10.0 * i as f32 + 0.25, 0.25, 0.0), Vec3::new(0.0, 0.0, 1.0));
+            assert!((instance.intersect(&ray).unwrap().dist - 5.0).abs() < 1e-5);
+
+            let miss = Ray::new(Vec3::new(10.0 * i as f32 + 5.0, 0.25, 0.0), Vec3::new(0.0, 0.0, 1.0));
+            assert!(instance.intersect(&miss).is_none());
+        }
+        assert_eq!(Arc::strong_count(&mesh), 11);
+    }
+
+    #[test]
+    fn test_instance_moves_like_a_shape() {
+        let vertices = vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        let mesh = TriangleMesh::new(vertices, vec![[0, 1, 2]]);
+        let mut instance = Instance::new(Arc::new(mesh.clone()), Transform::identity());
+        let mut moved = mesh;
+
+        for shape in [&mut instance as &mut dyn Shape, &mut moved as &mut dyn Shape] {
+            shape.rotate(0.3, 1.0, -0.5);
+            shape.translate(&Vec3::new(1.0, 2.0, 3.0));
+        }
+
+        let ray = Ray::new(Vec3::new(1.3, 2.3, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        let expected = moved.intersect(&ray).unwrap();
+        let actual = instance.intersect(&ray).unwrap();
+        assert!((actual.dist - expected.dist).abs() < 1e-4);
+        assert!((actual.normal - expected.normal).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_rotated_quad_keeps_its_edges() {
+        let quad = || Quad::new(Vec3::new(10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut instance = Instance::new(Arc::new(quad()), Transform::identity());
+        let mut rotated = quad();
+
+        for shape in [&mut instance as &mut dyn Shape, &mut rotated as &mut dyn Shape] {
+            shape.rotate(0.0, 0.0, std::f32::consts::PI / 4.0);
+        }
+
+        // turned about its center, the box keeps its size
+        assert!((rotated.center - Vec3::new(10.0, 0.0, 0.0)).norm() < 1e-5);
+        for edge in [rotated.width, rotated.height, rotated.depth] {
+            assert!((edge.norm() - 1.0).abs() < 1e-5, "{:?}", edge);
+        }
+
+        // the corner now points along x, half a diagonal away from the center
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let expected = rotated.intersect(&ray).unwrap();
+        let actual = instance.intersect(&ray).unwrap();
+        assert!((expected.dist - (10.0 - 0.5_f32.sqrt())).abs() < 1e-4, "{:?}", expected);
+        assert!((actual.dist - expected.dist).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_animated_instance() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0));
+        let start = Transform::translation(&Vec3::new(0.0, 0.0, 10.0));
+        let end = Transform::translation(&Vec3::new(4.0, 0.0, 10.0));
+        let mut instance = Instance::animated(sphere, start, 0.0, end, 1.0);
+
+        let ray = |x: f32, time: f32| Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)).at_time(time);
+        assert!(instance.intersect(&ray(0.0, 0.0)).is_some());
+        assert!(instance.intersect(&ray(4.0, 0.0)).is_none());
+        assert!(instance.intersect(&ray(2.0, 0.5)).is_some());
+        assert!(instance.intersect(&ray(0.0, 1.0)).is_none());
+        // still after the end of its motion
+        assert!(instance.intersect(&ray(4.0, 3.0)).is_some());
+
+        let bounds = instance.bounding_box();
+        assert!((bounds.min - Vec3::new(-1.0, -1.0, 9.0)).norm() < 1e-5);
+        assert!((bounds.max - Vec3::new(5.0, 1.0, 11.0)).norm() < 1e-5);
+
+        // moving the instance moves its whole path
+        instance.translate(&Vec3::new(0.0, 10.0, 0.0));
+        let moved = |x: f32, time: f32| Ray::new(Vec3::new(x, 10.0, 0.0), Vec3::new(0.0, 0.0, 1.0)).at_time(time);
+        assert!(instance.intersect(&moved(0.0, 0.0)).is_some());
+        assert!(instance.intersect(&moved(4.0, 1.0)).is_some());
+        assert!(instance.is_animated());
+    }
+}
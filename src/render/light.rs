@@ -0,0 +1,303 @@
+use std::f32::consts::PI;
+
+use crate::math::Vec3;
+
+use super::objects::{Color, Diamond, Sphere};
+use super::sampling;
+
+/// Light source. Lights only illuminate surfaces, rays going through them don't see them.
+///
+/// Lights emit `color` times a radiometric quantity, scene units being meters:
+/// radiant intensity (W/sr) for point and spot lights, irradiance (W/m²) for directional lights
+/// and radiance (W/(sr·m²)) for area lights. `with_power` sets it from the total power instead.
+#[derive(Debug, Clone, Copy)]
+pub enum Light {
+    /// Light radiating from a single point, casting hard shadows.
+    Point { position: Vec3, color: Color, intensity: f32 },
+    /// Parallel light coming from infinitely far away, such as the sun,
+    /// `direction` being the direction it travels in.
+    Directional { direction: Vec3, color: Color, irradiance: f32 },
+    /// Point light restricted to a cone around `direction` of half-angle `angle`, in degrees.
+    /// It fades out over the outer `falloff` fraction of the angle.
+    Spot { position: Vec3, direction: Vec3, angle: f32, falloff: f32, color: Color, intensity: f32 },
+    /// Lambertian rectangle emitting on the side its normal (width x height) points to,
+    /// casting soft shadows.
+    Rect { rect: Diamond, color: Color, radiance: f32 },
+    /// Lambertian sphere emitting all around it, casting soft shadows.
+    Sphere { sphere: Sphere, color: Color, radiance: f32 },
+}
+
+/// Light arriving at a point from one point of a light source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightSample {
+    /// Unit direction from the lit point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light, infinite for directional lights.
+    pub distance: f32,
+    /// Radiance arriving along `direction` over the probability density of the sample,
+    /// the light reflected by a surface being `brdf * radiance * cos`.
+    pub radiance: Color,
+}
+
+impl Light {
+    pub fn point(position: Vec3, color: Color, intensity: f32) -> Light {
+        Light::Point { position, color, intensity }
+    }
+
+    pub fn directional(direction: Vec3, color: Color, irradiance: f32) -> Light {
+        Light::Directional { direction: direction.normalize(), color, irradiance }
+    }
+
+    /// Spot light with a cone of half-angle `angle` degrees, whose outer fifth fades out.
+    pub fn spot(position: Vec3, direction: Vec3, angle: f32, color: Color, intensity: f32) -> Light {
+        Light::Spot { position, direction: direction.normalize(), angle, falloff: 0.2, color, intensity }
+    }
+
+    pub fn rect(rect: Diamond, color: Color, radiance: f32) -> Light {
+        Light::Rect { rect, color, radiance }
+    }
+
+    pub fn sphere(center: Vec3, radius: f32, color: Color, radiance: f32) -> Light {
+        Light::Sphere { sphere: Sphere::new(center, radius), color, radiance }
+    }
+
+    /// Fraction of the angle of a spot light over which it fades out, in [0, 1].
+    /// Other lights are left untouched.
+    pub fn with_falloff(self, falloff: f32) -> Light {
+        match self {
+            Light::Spot { position, direction, angle, color, intensity, .. } => {
+                Light::Spot { position, direction, angle, falloff: falloff.clamp(0.0, 1.0), color, intensity }
+            },
+            light => light,
+        }
+    }
+
+    /// Sets how bright the light is from the total power it emits, in watts.
+    /// Directional lights don't have a finite power and are left untouched.
+    pub fn with_power(self, power: f32) -> Light {
+        match self {
+            Light::Point { position, color, .. } => {
+                Light::Point { position, color, intensity: power / (4.0 * PI) }
+            },
+            Light::Spot { position, direction, angle, falloff, color, .. } => {
+                // the smooth edge counts for half
+                let cos_outer = angle.to_radians().cos();
+                let cos_inner = (angle * (1.0 - falloff)).to_radians().cos();
+                let solid_angle = 2.0 * PI * (1.0 - 0.5 * (cos_inner + cos_outer));
+
+                Light::Spot { position, direction, angle, falloff, color, intensity: power / solid_angle }
+            },
+            Light::Rect { rect, color, .. } => {
+                let area = rect.width.cross_product(&rect.height).norm();
+                Light::Rect { rect, color, radiance: power / (PI * area) }
+            },
+            Light::Sphere { sphere, color, .. } => {
+                let area = 4.0 * PI * sphere.radius * sphere.radius;
+                Light::Sphere { sphere, color, radiance: power / (PI * area) }
+            },
+            light => light,
+        }
+    }
+
+    /// Moves the light, directional lights have no position.
+    pub fn translate(&mut self, d_pos: &Vec3) {
+        match self {
+            Light::Point { position, .. } | Light::Spot { position, .. } => *position += *d_pos,
+            Light::Rect { rect, .. } => rect.center += *d_pos,
+            Light::Sphere { sphere, .. } => sphere.center += *d_pos,
+            Light::Directional { .. } => {},
+        }
+    }
+
+    /// Whether the light is a single point or direction, a single sample then tells all about it.
+    pub fn is_delta(&self) -> bool {
+        matches!(self, Light::Point { .. } | Light::Directional { .. } | Light::Spot { .. })
+    }
+
+    /// Light arriving at `point` from a point of the light picked with the two uniform numbers
+    /// `u1` and `u2`, ignored by delta lights. `None` when the light doesn't shine on `point`.
+    pub fn sample(&self, point: &Vec3, u1: f32, u2: f32) -> Option<LightSample> {
+        match *self {
+            Light::Point { position, color, intensity } => Some(from_point(point, &position, color * intensity)),
+            Light::Directional { direction, color, irradiance } => Some(LightSample {
+                direction: -direction,
+                distance: f32::INFINITY,
+                radiance: color * irradiance,
+            }),
+            Light::Spot { position, direction, angle, falloff, color, intensity } => {
+                let sample = from_point(point, &position, color * intensity);
+
+                let cos = -sample.direction.dot(&direction);
+                let cos_outer = angle.to_radians().cos();
+                let cos_inner = (angle * (1.0 - falloff)).to_radians().cos();
+
+                let attenuation = if cos >= cos_inner {
+                    1.0
+                } else if cos <= cos_outer {
+                    return None;
+                } else {
+                    let t = (cos - cos_outer) / (cos_inner - cos_outer);
+                    t * t * (3.0 - 2.0 * t)
+                };
+
+                Some(LightSample { radiance: sample.radiance * attenuation, ..sample })
+            },
+            Light::Rect { rect, color, radiance } => {
+                let position = rect.center + (u1 - 0.5) * rect.width + (u2 - 0.5) * rect.height;
+                let normal = rect.width.cross_product(&rect.height);
+                let area = normal.norm();
+
+                // uniform over the area: the radiance is weighted by the solid angle of the rectangle
+                let sample = from_point(point, &position, color * (radiance * area));
+                let cos = -sample.direction.dot(&(normal / area));
+                if cos <= 0.0 {
+                    return None;
+                }
+
+                Some(LightSample { radiance: sample.radiance * cos, ..sample })
+            },
+            Light::Sphere { sphere, color, radiance } => {
+                let to_center = sphere.center - *point;
+                let center_distance = to_center.norm();
+
+                // from far away, a sphere looks like a disk of the same radius
+                if center_distance <= sphere.radius {
+                    let intensity = radiance * PI * sphere.radius * sphere.radius;
+                    return Some(from_point(point, &sphere.center, color * intensity));
+                }
+
+                // uniform direction in the cone the sphere is seen in
+                let w = to_center / center_distance;
+                let sin2_max = (sphere.radius / center_distance).powi(2);
+                let cos_max = (1.0 - sin2_max).sqrt();
+                // 1 - cos_max, without cancellation for distant spheres
+                let cone = sin2_max / (1.0 + cos_max);
+
+                let cos_theta = 1.0 - u1 * cone;
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u2;
+
+                let (tangent, bitangent) = sampling::orthonormal_basis(&w);
+                let direction = (sin_theta * phi.cos()) * tangent + (sin_theta * phi.sin()) * bitangent + cos_theta * w;
+
+                // nearest intersection of the direction with the sphere
+                let half_chord = (sphere.radius * sphere.radius - center_distance * center_distance * sin_theta * sin_theta).max(0.0).sqrt();
+                let distance = center_distance * cos_theta - half_chord;
+
+                Some(LightSample { direction, distance, radiance: color * (radiance * 2.0 * PI * cone) })
+            },
+        }
+    }
+}
+
+/// Light arriving at `point` from a point light of the given intensity at `position`,
+/// falling off with the square of the distance.
+fn from_point(point: &Vec3, position: &Vec3, intensity: Color) -> LightSample {
+    let to_light = *position - *point;
+    let distance = to_light.norm();
+
+    LightSample {
+        direction: to_light / distance,
+        distance,
+        radiance: intensity * (1.0 / (distance * distance)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color { rgb: Vec3 { x: 1.0, y: 1.0, z: 1.0 } };
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() <= 1e-4 * b.abs().max(1.0), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_point_light_falloff() {
+        let light = Light::point(Vec3::new(0.0, 200.0, 0.0), WHITE, 1000.0);
+        let sample = light.sample(&Vec3::new(0.0, 0.0, 0.0), 0.5, 0.5).unwrap();
+
+        assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, 200.0);
+        assert_close(sample.radiance.rgb.x, 0.025);
+
+        // twice as far, four times dimmer
+        let far = light.sample(&Vec3::new(0.0, -200.0, 0.0), 0.5, 0.5).unwrap();
+        assert_close(far.radiance.rgb.x, 0.025 / 4.0);
+    }
+
+    #[test]
+    fn test_directional_light() {
+        let light = Light::directional(Vec3::new(0.0, -2.0, 0.0), WHITE, 3.0);
+        let sample = light.sample(&Vec3::new(50.0, 0.0, -300.0), 0.1, 0.9).unwrap();
+
+        assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, f32::INFINITY);
+        assert_eq!(sample.radiance, WHITE * 3.0);
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = Light::spot(Vec3::new(0.0, 100.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 30.0, WHITE, 10000.0)
+            .with_falloff(0.5);
+        let lit = |x: f32| light.sample(&Vec3::new(x, 0.0, 0.0), 0.5, 0.5).map(|sample| sample.radiance.rgb.x);
+
+        // on the axis, the fading part of the cone and outside of it
+        assert_eq!(lit(0.0), Some(1.0));
+        let fading = lit(100.0 * 22.5f32.to_radians().tan()).unwrap();
+        assert!(fading > 0.0 && fading < 1.0);
+        assert_eq!(lit(100.0), None);
+    }
+
+    #[test]
+    fn test_power() {
+        let point = Light::point(Vec3::new(0.0, 0.0, 0.0), WHITE, 1.0).with_power(4.0 * PI);
+        assert!(matches!(point, Light::Point { intensity, .. } if (intensity - 1.0).abs() < 1e-6));
+
+        let rect = Diamond::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.5));
+        let rect = Light::rect(rect, WHITE, 0.0).with_power(PI);
+        assert!(matches!(rect, Light::Rect { radiance, .. } if (radiance - 1.0).abs() < 1e-6));
+
+        let sun = Light::directional(Vec3::new(0.0, -1.0, 0.0), WHITE, 2.0).with_power(100.0);
+        assert!(matches!(sun, Light::Directional { irradiance, .. } if irradiance == 2.0));
+    }
+
+    #[test]
+    fn test_rect_light_is_one_sided() {
+        // facing down
+        let rect = Diamond::new(Vec3::new(0.0, 100.0, 0.0), Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 10.0));
+        let light = Light::rect(rect, WHITE, 1.0);
+
+        for (u1, u2) in [(0.0, 0.0), (0.3, 0.8), (1.0, 1.0)] {
+            let sample = light.sample(&Vec3::new(0.0, 0.0, 0.0), u1, u2).unwrap();
+            let position = sample.distance * sample.direction;
+            assert!((position.y - 100.0).abs() < 1e-3);
+            assert!(position.x.abs() <= 5.0 + 1e-3 && position.z.abs() <= 5.0 + 1e-3);
+        }
+
+        // right below the center: radiance times the solid angle of the small rectangle
+        let center = light.sample(&Vec3::new(0.0, 0.0, 0.0), 0.5, 0.5).unwrap();
+        assert_close(center.radiance.rgb.x, 100.0 / (100.0 * 100.0));
+
+        assert!(light.sample(&Vec3::new(0.0, 200.0, 0.0), 0.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn test_sphere_light_samples_visible_surface() {
+        let light = Light::sphere(Vec3::new(0.0, 0.0, 100.0), 10.0, WHITE, 1.0);
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+
+        for i in 0..50 {
+            let sample = light.sample(&origin, (i as f32 + 0.5) / 50.0, (i * 7 % 50) as f32 / 50.0).unwrap();
+            let position = sample.distance * sample.direction;
+
+            // on the surface, on the side facing the point
+            assert!(((position - Vec3::new(0.0, 0.0, 100.0)).norm() - 10.0).abs() < 1e-2);
+            assert!(position.z <= 100.0);
+
+            // radiance times the solid angle of the sphere, roughly that of a disk
+            assert!((sample.radiance.rgb.x - PI * 0.01).abs() < 0.01 * PI * 0.01);
+        }
+    }
+}
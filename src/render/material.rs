@@ -0,0 +1,712 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rand::{Rng, RngCore};
+
+use crate::math::Vec3;
+
+use super::objects::{Color, Intersection};
+use super::sampling;
+use super::texture::Texture;
+
+/// How a surface reacts to light.
+///
+/// Directions given to the methods point away from the surface,
+/// and `intersection.normal` is on the same side as the viewer.
+pub trait Material: Send + Sync {
+    /// Diffuse reflectance.
+    fn albedo(&self, intersection: &Intersection) -> Color;
+
+    /// BRDF: fraction of the light arriving from `light_dir` that is reflected towards `view_dir`.
+    fn eval(&self, intersection: &Intersection, view_dir: &Vec3, light_dir: &Vec3) -> Color;
+
+    /// Picks a direction to continue a light path in, along with the weight of the path
+    /// (BRDF times cosine over the probability density of the direction).
+    /// Defaults to cosine-weighted sampling of the hemisphere around the normal.
+    fn sample(&self, intersection: &Intersection, view_dir: &Vec3, rng: &mut dyn RngCore) -> Option<(Vec3, Color)> {
+        let direction = sampling::cosine_hemisphere(&intersection.normal, rng.gen(), rng.gen());
+        let weight = self.eval(intersection, view_dir, &direction) * PI;
+
+        if weight.is_black() {
+            None
+        } else {
+            Some((direction, weight))
+        }
+    }
+
+    /// Probability density of `sample` picking `light_dir`, to weight it against the other
+    /// ways of picking directions towards the lights. Must match `sample`.
+    fn pdf(&self, intersection: &Intersection, _view_dir: &Vec3, light_dir: &Vec3) -> f32 {
+        intersection.normal.dot(light_dir).max(0.0) / PI
+    }
+
+    /// Normal the surface is shaded with, tilted by small scale relief.
+    /// Unlike for the other methods, `intersection.normal` is the geometric normal of the shape,
+    /// the result is turned towards the viewer afterwards.
+    fn shading_normal(&self, intersection: &Intersection) -> Vec3 {
+        intersection.normal
+    }
+
+    /// Light given off by the surface itself.
+    fn emitted(&self, _intersection: &Intersection) -> Color {
+        Color::black()
+    }
+
+    /// Perfectly specular behaviour, followed by recursive rays instead of being shaded with `eval`.
+    fn specular(&self) -> Option<Specular> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Specular {
+    /// Perfect mirror, reflected light is filtered by the colour.
+    Mirror(Color),
+    /// Transparent material (glass, water...) of the given index of refraction,
+    /// transmitted light is filtered by `tint`.
+    Dielectric { ior: f32, tint: Color },
+}
+
+/// Schlick's approximation of the Fresnel reflectance when light hits an interface
+/// with an angle of cosine `cos_i`, `eta` being the ratio of the refractive indices
+/// (incident over transmitted side).
+pub fn schlick(cos_i: f32, eta: f32) -> f32 {
+    let r0 = ((eta - 1.0) / (eta + 1.0)).powi(2);
+
+    // leaving a denser medium, the approximation holds for the transmitted angle
+    let cos = if eta > 1.0 {
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        if sin2_t > 1.0 {
+            return 1.0;
+        }
+        (1.0 - sin2_t).sqrt()
+    } else {
+        cos_i
+    };
+
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
+
+/// Step over which the slopes of bump maps are measured.
+const BUMP_DELTA: f32 = 1e-3;
+
+/// Small scale relief of a surface (bricks, scratches...) that tilts its shading normal
+/// instead of adding geometry.
+#[derive(Debug, Clone)]
+pub enum Relief {
+    /// Tangent space normals encoded as colours, channels in [0, 1] mapping to [-1, 1]:
+    /// red along the tangent, green along the bitangent and blue along the normal.
+    NormalMap(Arc<dyn Texture>),
+    /// Height field given by the brightness of a texture. Its slopes are measured over
+    /// texture coordinates, or over space for solid textures, and scaled by `strength`.
+    Bump { height: Arc<dyn Texture>, strength: f32 },
+}
+
+impl Relief {
+    /// Perturbed unit normal at `intersection`.
+    pub fn normal(&self, intersection: &Intersection) -> Vec3 {
+        let (tangent, bitangent) = intersection.tangent_frame();
+        let normal = intersection.normal;
+        let [u, v] = intersection.uv;
+
+        let perturbed = match self {
+            Relief::NormalMap(texture) => {
+                let rgb = texture.value(u, v, &intersection.point).rgb;
+                (2.0 * rgb.x - 1.0) * tangent + (2.0 * rgb.y - 1.0) * bitangent + (2.0 * rgb.z - 1.0) * normal
+            },
+            Relief::Bump { height, strength } => {
+                let height_at = |u: f32, v: f32, point: Vec3| {
+                    let rgb = height.value(u, v, &point).rgb;
+                    (rgb.x + rgb.y + rgb.z) / 3.0
+                };
+
+                let point = intersection.point;
+                let base = height_at(u, v, point);
+                let slope_u = (height_at(u + BUMP_DELTA, v, point + BUMP_DELTA * tangent) - base) / BUMP_DELTA;
+                let slope_v = (height_at(u, v + BUMP_DELTA, point + BUMP_DELTA * bitangent) - base) / BUMP_DELTA;
+
+                normal - *strength * (slope_u * tangent + slope_v * bitangent)
+            },
+        };
+
+        if perturbed.norm2() > 0.0 {
+            perturbed.normalize()
+        } else {
+            normal
+        }
+    }
+}
+
+/// Lambertian diffuse plus Blinn-Phong specular highlight, optionally glowing.
+#[derive(Debug, Clone)]
+pub struct Phong {
+    pub diffuse: Color,
+    /// Modulates `diffuse` over the surface.
+    pub texture: Option<Arc<dyn Texture>>,
+    pub relief: Option<Relief>,
+    pub specular: Color,
+    pub shininess: f32,
+    pub emissive: Color,
+}
+
+impl Phong {
+    /// Matte material of the given colour.
+    pub fn new(diffuse: Color) -> Phong {
+        Phong {
+            diffuse,
+            texture: None,
+            relief: None,
+            specular: Color::black(),
+            shininess: 0.0,
+            emissive: Color::black(),
+        }
+    }
+
+    pub fn with_specular(self, specular: Color, shininess: f32) -> Phong {
+        Phong { specular, shininess, ..self }
+    }
+
+    pub fn with_emissive(self, emissive: Color) -> Phong {
+        Phong { emissive, ..self }
+    }
+
+    pub fn with_texture(self, texture: Arc<dyn Texture>) -> Phong {
+        Phong { texture: Some(texture), ..self }
+    }
+
+    pub fn with_relief(self, relief: Relief) -> Phong {
+        Phong { relief: Some(relief), ..self }
+    }
+
+    /// Diffuse colour at the point of the surface.
+    fn diffuse_at(&self, intersection: &Intersection) -> Color {
+        match &self.texture {
+            Some(texture) => {
+                let [u, v] = intersection.uv;
+                self.diffuse * texture.value(u, v, &intersection.point)
+            },
+            None => self.diffuse,
+        }
+    }
+}
+
+impl Default for Phong {
+    fn default() -> Self {
+        Phong::new(Color::new(1.0, 1.0, 1.0))
+    }
+}
+
+impl Material for Phong {
+    fn albedo(&self, intersection: &Intersection) -> Color {
+        self.diffuse_at(intersection)
+    }
+
+    fn eval(&self, intersection: &Intersection, view_dir: &Vec3, light_dir: &Vec3) -> Color {
+        let diffuse = self.diffuse_at(intersection) * (1.0 / PI);
+
+        if self.specular.is_black() {
+            return diffuse;
+        }
+
+        // normalized so that the highlight reflects the same energy whatever its shininess
+        let halfway = (*view_dir + *light_dir).normalize();
+        let highlight = intersection.normal.dot(&halfway).max(0.0).powf(self.shininess)
+            * (self.shininess + 8.0) / (8.0 * PI);
+
+        diffuse + self.specular * highlight
+    }
+
+    fn shading_normal(&self, intersection: &Intersection) -> Vec3 {
+        match &self.relief {
+            Some(relief) => relief.normal(intersection),
+            None => intersection.normal,
+        }
+    }
+
+    fn emitted(&self, _intersection: &Intersection) -> Color {
+        self.emissive
+    }
+}
+
+/// Physically based material of glTF, parameterized by metallic and roughness: Cook-Torrance
+/// microfacet reflection (GGX distribution, Smith shadowing, Schlick Fresnel) over a Lambertian base.
+///
+/// Dielectrics reflect 4% of the light at normal incidence and diffuse the rest in `base_color`,
+/// metals reflect it all tinted by `base_color`, `metallic` blending between the two.
+#[derive(Debug, Clone)]
+pub struct Pbr {
+    pub base_color: Color,
+    /// Modulates `base_color` over the surface.
+    pub texture: Option<Arc<dyn Texture>>,
+    pub relief: Option<Relief>,
+    /// 0 for dielectrics, 1 for metals.
+    pub metallic: f32,
+    /// Perceptual roughness, from 0 (polished) to 1 (rough), squared into the width of the GGX distribution.
+    pub roughness: f32,
+    pub emissive: Color,
+}
+
+/// Narrowest GGX distribution, a perfectly smooth surface would need a Dirac.
+const MIN_ALPHA: f32 = 1e-3;
+
+impl Pbr {
+    /// Dielectric of the given colour and medium roughness.
+    pub fn new(base_color: Color) -> Pbr {
+        Pbr {
+            base_color,
+            texture: None,
+            relief: None,
+            metallic: 0.0,
+            roughness: 0.5,
+            emissive: Color::black(),
+        }
+    }
+
+    pub fn metal(base_color: Color, roughness: f32) -> Pbr {
+        Pbr::new(base_color).with_metallic(1.0).with_roughness(roughness)
+    }
+
+    pub fn with_metallic(self, metallic: f32) -> Pbr {
+        Pbr { metallic: metallic.clamp(0.0, 1.0), ..self }
+    }
+
+    pub fn with_roughness(self, roughness: f32) -> Pbr {
+        Pbr { roughness: roughness.clamp(0.0, 1.0), ..self }
+    }
+
+    pub fn with_emissive(self, emissive: Color) -> Pbr {
+        Pbr { emissive, ..self }
+    }
+
+    pub fn with_texture(self, texture: Arc<dyn Texture>) -> Pbr {
+        Pbr { texture: Some(texture), ..self }
+    }
+
+    pub fn with_relief(self, relief: Relief) -> Pbr {
+        Pbr { relief: Some(relief), ..self }
+    }
+
+    fn base_color_at(&self, intersection: &Intersection) -> Color {
+        match &self.texture {
+            Some(texture) => {
+                let [u, v] = intersection.uv;
+                self.base_color * texture.value(u, v, &intersection.point)
+            },
+            None => self.base_color,
+        }
+    }
+
+    fn alpha(&self) -> f32 {
+        (self.roughness * self.roughness).max(MIN_ALPHA)
+    }
+
+    /// Reflectance at normal incidence.
+    fn f0(&self, base_color: Color) -> Color {
+        Color::new(0.04, 0.04, 0.04) * (1.0 - self.metallic) + base_color * self.metallic
+    }
+
+    /// Probability of sampling the specular lobe rather than the diffuse one,
+    /// after their share of the reflected light seen from `cos_v`.
+    fn specular_probability(&self, base_color: Color, cos_v: f32) -> f32 {
+        let specular = fresnel(self.f0(base_color), cos_v).luminance();
+        let diffuse = base_color.luminance() * (1.0 - self.metallic) * (1.0 - specular);
+
+        if specular + diffuse > 0.0 {
+            (specular / (specular + diffuse)).clamp(0.1, 1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Schlick's Fresnel for a reflectance `f0` at normal incidence.
+fn fresnel(f0: Color, cos: f32) -> Color {
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+/// GGX (Trowbridge-Reitz) density of microfacet normals at cosine `cos_h` from the normal.
+fn ggx_distribution(cos_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = cos_h * cos_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+/// Smith masking of GGX microfacets seen at cosine `cos` from the normal.
+fn smith_g1(cos: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    2.0 * cos / (cos + (a2 + (1.0 - a2) * cos * cos).sqrt())
+}
+
+/// Microfacet normal of the GGX distribution visible from `view`, in the local frame of the
+/// surface where the normal is z (Heitz, "Sampling the GGX Distribution of Visible Normals").
+fn sample_visible_normal(view: Vec3, alpha: f32, u1: f32, u2: f32) -> Vec3 {
+    // the view in the frame where the distribution is a hemisphere
+    let stretched = Vec3::new(alpha * view.x, alpha * view.y, view.z).normalize();
+
+    let length2 = stretched.x * stretched.x + stretched.y * stretched.y;
+    let t1 = if length2 > 0.0 {
+        (1.0 / length2.sqrt()) * Vec3::new(-stretched.y, stretched.x, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = stretched.cross_product(&t1);
+
+    // point of the disk, squeezed into the part of the hemisphere that is visible
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + stretched.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+    let normal = p1 * t1 + p2 * t2 + p3 * stretched;
+    Vec3::new(alpha * normal.x, alpha * normal.y, normal.z.max(0.0)).normalize()
+}
+
+impl Material for Pbr {
+    fn albedo(&self, intersection: &Intersection) -> Color {
+        self.base_color_at(intersection) * (1.0 - self.metallic)
+    }
+
+    fn eval(&self, intersection: &Intersection, view_dir: &Vec3, light_dir: &Vec3) -> Color {
+        let normal = intersection.normal;
+        let cos_l = normal.dot(light_dir);
+        let cos_v = normal.dot(view_dir);
+
+        if cos_l <= 0.0 || cos_v <= 0.0 {
+            return Color::black();
+        }
+
+        let base_color = self.base_color_at(intersection);
+        let alpha = self.alpha();
+        let halfway = (*view_dir + *light_dir).normalize();
+
+        let f = fresnel(self.f0(base_color), view_dir.dot(&halfway));
+        let specular = f * (ggx_distribution(normal.dot(&halfway), alpha) * smith_g1(cos_v, alpha) * smith_g1(cos_l, alpha)
+            / (4.0 * cos_v * cos_l));
+
+        // light not reflected by the coating reaches the diffuse base
+        let diffuse = (Color::new(1.0, 1.0, 1.0) - f) * base_color * ((1.0 - self.metallic) / PI);
+
+        diffuse + specular
+    }
+
+    /// Picks the specular or the diffuse lobe, then a visible microfacet normal to reflect
+    /// the view about or a cosine-weighted direction.
+    fn sample(&self, intersection: &Intersection, view_dir: &Vec3, rng: &mut dyn RngCore) -> Option<(Vec3, Color)> {
+        let normal = intersection.normal;
+        let cos_v = normal.dot(view_dir);
+        if cos_v <= 0.0 {
+            return None;
+        }
+
+        let probability = self.specular_probability(self.base_color_at(intersection), cos_v);
+        let (u1, u2) = (rng.gen(), rng.gen());
+
+        let direction = if rng.gen::<f32>() < probability {
+            let (tangent, bitangent) = sampling::orthonormal_basis(&normal);
+            let local_view = Vec3::new(view_dir.dot(&tangent), view_dir.dot(&bitangent), cos_v);
+            let h = sample_visible_normal(local_view, self.alpha(), u1, u2);
+            let halfway = h.x * tangent + h.y * bitangent + h.z * normal;
+
+            (-*view_dir).reflect(&halfway)
+        } else {
+            sampling::cosine_hemisphere(&normal, u1, u2)
+        };
+
+        let cos_l = normal.dot(&direction);
+        let pdf = self.pdf(intersection, view_dir, &direction);
+        if cos_l <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+
+        let weight = self.eval(intersection, view_dir, &direction) * (cos_l / pdf);
+        if weight.is_black() {
+            None
+        } else {
+            Some((direction, weight))
+        }
+    }
+
+    fn pdf(&self, intersection: &Intersection, view_dir: &Vec3, light_dir: &Vec3) -> f32 {
+        let normal = intersection.normal;
+        let cos_l = normal.dot(light_dir);
+        let cos_v = normal.dot(view_dir);
+
+        if cos_l <= 0.0 || cos_v <= 0.0 {
+            return 0.0;
+        }
+
+        let alpha = self.alpha();
+        let halfway = (*view_dir + *light_dir).normalize();
+
+        // density of the visible normals, over that of the reflected directions
+        let specular = smith_g1(cos_v, alpha) * ggx_distribution(normal.dot(&halfway), alpha) / (4.0 * cos_v);
+        let diffuse = cos_l / PI;
+
+        let probability = self.specular_probability(self.base_color_at(intersection), cos_v);
+        probability * specular + (1.0 - probability) * diffuse
+    }
+
+    fn shading_normal(&self, intersection: &Intersection) -> Vec3 {
+        match &self.relief {
+            Some(relief) => relief.normal(intersection),
+            None => intersection.normal,
+        }
+    }
+
+    fn emitted(&self, _intersection: &Intersection) -> Color {
+        self.emissive
+    }
+}
+
+/// Perfect mirror, such as chrome.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mirror {
+    pub tint: Color,
+}
+
+impl Mirror {
+    pub fn new(tint: Color) -> Mirror {
+        Mirror { tint }
+    }
+}
+
+impl Material for Mirror {
+    fn albedo(&self, _intersection: &Intersection) -> Color {
+        Color::black()
+    }
+
+    fn eval(&self, _intersection: &Intersection, _view_dir: &Vec3, _light_dir: &Vec3) -> Color {
+        Color::black()
+    }
+
+    fn specular(&self) -> Option<Specular> {
+        Some(Specular::Mirror(self.tint))
+    }
+}
+
+/// Smooth transparent material, reflecting and refracting light according to the Fresnel equations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dielectric {
+    pub ior: f32,
+    pub tint: Color,
+}
+
+impl Dielectric {
+    pub fn new(ior: f32) -> Dielectric {
+        Dielectric { ior, tint: Color::new(1.0, 1.0, 1.0) }
+    }
+
+    pub fn glass() -> Dielectric {
+        Dielectric::new(1.5)
+    }
+
+    pub fn with_tint(self, tint: Color) -> Dielectric {
+        Dielectric { tint, ..self }
+    }
+}
+
+impl Material for Dielectric {
+    fn albedo(&self, _intersection: &Intersection) -> Color {
+        Color::black()
+    }
+
+    fn eval(&self, _intersection: &Intersection, _view_dir: &Vec3, _light_dir: &Vec3) -> Color {
+        Color::black()
+    }
+
+    fn specular(&self) -> Option<Specular> {
+        Some(Specular::Dielectric { ior: self.ior, tint: self.tint })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::texture::{Checker, Constant, Gradient, Noise};
+
+    fn intersection() -> Intersection {
+        Intersection {
+            point: Vec3::new(0.0, 0.0, 0.0),
+            dist: 1.0,
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            uv: [0.0, 0.0],
+            tangent: Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn test_phong_diffuse_only() {
+        let material = Phong::new(Color::new(0.5, 0.25, 1.0));
+        let view = Vec3::new(0.0, 0.0, 1.0);
+        let light = Vec3::new(1.0, 0.0, 1.0).normalize();
+
+        assert_eq!(material.eval(&intersection(), &view, &light), Color::new(0.5, 0.25, 1.0) * (1.0 / PI));
+        assert_eq!(material.albedo(&intersection()), Color::new(0.5, 0.25, 1.0));
+        assert!(material.emitted(&intersection()).is_black());
+    }
+
+    #[test]
+    fn test_phong_highlight() {
+        let material = Phong::new(Color::black()).with_specular(Color::new(1.0, 1.0, 1.0), 20.0);
+        let view = Vec3::new(1.0, 0.0, 1.0).normalize();
+
+        // mirror direction: the halfway vector is the normal
+        let mirror = Vec3::new(-1.0, 0.0, 1.0).normalize();
+        let peak = material.eval(&intersection(), &view, &mirror);
+        assert!((peak.rgb.x - 28.0 / (8.0 * PI)).abs() < 1e-5);
+
+        let off = material.eval(&intersection(), &view, &Vec3::new(0.0, 0.0, 1.0));
+        assert!(off.rgb.x < peak.rgb.x);
+    }
+
+    #[test]
+    fn test_phong_emissive() {
+        let material = Phong::default().with_emissive(Color::new(2.0, 2.0, 2.0));
+        assert_eq!(material.emitted(&intersection()), Color::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn test_phong_texture() {
+        let checker = Checker::new(Color::new(1.0, 1.0, 1.0), Color::black(), 2.0);
+        let material = Phong::new(Color::new(0.5, 0.25, 1.0)).with_texture(Arc::new(checker));
+
+        let mut hit = intersection();
+        hit.uv = [0.25, 0.25];
+        assert_eq!(material.albedo(&hit), Color::new(0.5, 0.25, 1.0));
+
+        hit.uv = [0.75, 0.25];
+        assert!(material.albedo(&hit).is_black());
+        assert!(material.eval(&hit, &hit.normal, &hit.normal).is_black());
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let flat = Relief::NormalMap(Arc::new(Constant(Color::new(0.5, 0.5, 1.0))));
+        assert_eq!(flat.normal(&intersection()), Vec3::new(0.0, 0.0, 1.0));
+
+        // tilted towards the bitangent, y since the tangent is x and the normal z
+        let tilted = Relief::NormalMap(Arc::new(Constant(Color::new(0.5, 1.0, 1.0))));
+        let expected = Vec3::new(0.0, 1.0, 1.0).normalize();
+        assert!((tilted.normal(&intersection()) - expected).norm() < 1e-6);
+
+        let material = Phong::default().with_relief(tilted);
+        assert!((material.shading_normal(&intersection()) - expected).norm() < 1e-6);
+        assert_eq!(Phong::default().shading_normal(&intersection()), Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_bump_map() {
+        // height growing with u: the normal leans back against the slope
+        let ramp = Gradient::horizontal(Color::black(), Color::new(1.0, 1.0, 1.0));
+        let mut hit = intersection();
+        hit.uv = [0.5, 0.5];
+
+        let bump = Relief::Bump { height: Arc::new(ramp), strength: 1.0 };
+        let expected = Vec3::new(-1.0, 0.0, 1.0).normalize();
+        assert!((bump.normal(&hit) - expected).norm() < 1e-3, "got {:?}", bump.normal(&hit));
+
+        let flat = Relief::Bump { height: Arc::new(Constant(Color::new(0.3, 0.3, 0.3))), strength: 5.0 };
+        assert_eq!(flat.normal(&hit), hit.normal);
+
+        // solid textures are differentiated over space
+        let noise = Relief::Bump { height: Arc::new(Noise::new(Color::black(), Color::new(1.0, 1.0, 1.0), 4.0, 3)), strength: 1.0 };
+        let tilted = (0..10).any(|i| {
+            hit.point = Vec3::new(i as f32 * 0.13, 0.2, 0.0);
+            (noise.normal(&hit) - hit.normal).norm() > 1e-2
+        });
+        assert!(tilted);
+    }
+
+    #[test]
+    fn test_lambert_sampling_weight_is_albedo() {
+        let material = Phong::new(Color::new(0.5, 0.25, 1.0));
+        let view = Vec3::new(0.0, 0.0, 1.0);
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let (direction, weight) = material.sample(&intersection(), &view, &mut rng).unwrap();
+            assert!(direction.z >= 0.0, "sampled direction below the surface");
+            assert!((weight.rgb - Vec3::new(0.5, 0.25, 1.0)).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_pbr_reflectance() {
+        let view = Vec3::new(1.0, 0.0, 1.0).normalize();
+        let mirror = Vec3::new(-1.0, 0.0, 1.0).normalize();
+        let aside = Vec3::new(0.0, 1.0, 0.2).normalize();
+
+        // metals have no diffuse part and a highlight tinted by the base colour
+        let gold = Pbr::metal(Color::new(1.0, 0.8, 0.3), 0.3);
+        assert!(gold.albedo(&intersection()).is_black());
+        let peak = gold.eval(&intersection(), &view, &mirror);
+        assert!(peak.rgb.x > peak.rgb.z && peak.rgb.x > 1.0, "got {:?}", peak);
+        assert!(gold.eval(&intersection(), &view, &aside).rgb.x < 0.01 * peak.rgb.x);
+
+        // the highlight of a dielectric is white, over its diffuse colour
+        let plastic = Pbr::new(Color::new(1.0, 0.0, 0.0)).with_roughness(0.3);
+        let peak = plastic.eval(&intersection(), &view, &mirror);
+        assert!(peak.rgb.y > 0.1 && peak.rgb.x > peak.rgb.y, "got {:?}", peak);
+        let diffuse = plastic.eval(&intersection(), &view, &aside);
+        assert!((diffuse.rgb.x - 0.96 / PI).abs() < 0.01 && diffuse.rgb.y < 1e-3, "got {:?}", diffuse);
+
+        // nothing goes through
+        assert!(plastic.eval(&intersection(), &view, &Vec3::new(0.0, 0.0, -1.0)).is_black());
+    }
+
+    #[test]
+    fn test_pbr_sampling_matches_eval() {
+        let mut rng = rand::thread_rng();
+        let view = Vec3::new(0.5, 0.2, 1.0).normalize();
+
+        for material in [Pbr::metal(Color::new(1.0, 1.0, 1.0), 0.4), Pbr::new(Color::new(0.5, 0.8, 0.2)).with_roughness(0.1)] {
+            let mut reflected = 0.0;
+            let count = 4000;
+
+            for _ in 0..count {
+                if let Some((direction, weight)) = material.sample(&intersection(), &view, &mut rng) {
+                    let pdf = material.pdf(&intersection(), &view, &direction);
+                    let expected = material.eval(&intersection(), &view, &direction) * (direction.z / pdf);
+                    assert!((weight.rgb - expected.rgb).norm() < 1e-4 * (1.0 + expected.rgb.norm()));
+                    reflected += weight.luminance() / count as f32;
+                }
+            }
+
+            // no energy created, little lost
+            assert!(reflected > 0.5 && reflected < 1.02, "{:?} reflects {}", material, reflected);
+        }
+    }
+
+    #[test]
+    fn test_pbr_pdf_integrates_to_samples() {
+        let mut rng = rand::thread_rng();
+        let view = Vec3::new(0.3, 0.0, 1.0).normalize();
+        let material = Pbr::metal(Color::new(1.0, 1.0, 1.0), 0.7);
+        let count = 20000;
+
+        // uniform sampling of the hemisphere, of density 1 / (2 PI)
+        let mut integral = 0.0;
+        for _ in 0..count {
+            let (z, phi) = (rng.gen::<f32>(), 2.0 * PI * rng.gen::<f32>());
+            let r = (1.0 - z * z).sqrt();
+            let direction = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            integral += material.pdf(&intersection(), &view, &direction) * 2.0 * PI / count as f32;
+        }
+
+        // short of 1 by the microfacets reflecting the view below the surface
+        let kept = (0..count).filter(|_| material.sample(&intersection(), &view, &mut rng).is_some()).count();
+        let kept = kept as f32 / count as f32;
+        assert!(kept > 0.7 && (integral - kept).abs() < 0.03, "got {} for {} of the samples", integral, kept);
+    }
+
+    #[test]
+    fn test_schlick() {
+        // air to glass: 4% at normal incidence, everything at grazing angles
+        assert!((schlick(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-6);
+        assert!((schlick(0.0, 1.0 / 1.5) - 1.0).abs() < 1e-6);
+        // glass to air past the critical angle
+        assert_eq!(schlick(0.5, 1.5), 1.0);
+    }
+}
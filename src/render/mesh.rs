@@ -0,0 +1,419 @@
+use crate::math::{Vec3, Quat};
+
+use super::bvh::{Aabb, Bvh};
+use super::objects::{Intersection, Ray, Shape};
+
+/// Below this determinant the ray is considered parallel to the triangle.
+const PARALLEL_EPSILON: f32 = 1e-8;
+
+/// Möller–Trumbore ray / triangle intersection.
+/// Returns the distance along the ray and the barycentric coordinates (u, v) of the hit
+/// point, which is `(1 - u - v) * a + u * b + v * c`.
+fn intersect_triangle(ray: &Ray, a: &Vec3, b: &Vec3, c: &Vec3) -> Option<(f32, f32, f32)> {
+    let edge_1 = *b - *a;
+    let edge_2 = *c - *a;
+
+    let p = ray.direction.cross_product(&edge_2);
+    let det = edge_1.dot(&p);
+
+    if det.abs() < PARALLEL_EPSILON {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = ray.origin - *a;
+
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross_product(&edge_1);
+    let v = ray.direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge_2.dot(&q) * inv_det;
+    if t < 0.0 {
+        return None;
+    }
+
+    Some((t, u, v))
+}
+
+/// Normal of the (a, b, c) triangle, oriented according to its counter-clockwise winding.
+fn face_normal(a: &Vec3, b: &Vec3, c: &Vec3) -> Vec3 {
+    (*b - *a).cross_product(&(*c - *a)).normalize()
+}
+
+/// Direction of increasing u over a triangle, from its `edge_1` (a to b) and `edge_2` (a to c)
+/// and the texture coordinates of its vertices. `None` when the coordinates are degenerate.
+fn uv_tangent(edge_1: &Vec3, edge_2: &Vec3, uv_a: [f32; 2], uv_b: [f32; 2], uv_c: [f32; 2]) -> Option<Vec3> {
+    let (du_1, dv_1) = (uv_b[0] - uv_a[0], uv_b[1] - uv_a[1]);
+    let (du_2, dv_2) = (uv_c[0] - uv_a[0], uv_c[1] - uv_a[1]);
+
+    // edge_1 = du_1 * tangent + dv_1 * bitangent, same for edge_2
+    let det = du_1 * dv_2 - du_2 * dv_1;
+
+    if det.abs() < PARALLEL_EPSILON {
+        None
+    } else {
+        Some((dv_2 * *edge_1 - dv_1 * *edge_2) / det)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Triangle {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub c: Vec3,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3) -> Triangle {
+        Triangle { a, b, c }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        face_normal(&self.a, &self.b, &self.c)
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.a + self.b + self.c) / 3.0
+    }
+}
+
+impl Shape for Triangle {
+    fn translate(&mut self, d_pos: &Vec3) {
+        self.a += *d_pos;
+        self.b += *d_pos;
+        self.c += *d_pos;
+    }
+
+    fn rotate_quat(&mut self, rotation: &Quat) {
+        let mat = rotation.to_mat3();
+        let centroid = self.centroid();
+
+        self.a = mat * (self.a - centroid) + centroid;
+        self.b = mat * (self.b - centroid) + centroid;
+        self.c = mat * (self.c - centroid) + centroid;
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (t, u, v) = intersect_triangle(ray, &self.a, &self.b, &self.c)?;
+
+        Some(Intersection {
+            point: ray.origin + t * ray.direction,
+            dist: t,
+            normal: self.normal(),
+            uv: [u, v],
+            tangent: (self.b - self.a).normalize(),
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(&[self.a, self.b, self.c])
+    }
+}
+
+/// Indexed triangle mesh: triangles share the vertices of a single buffer.
+///
+/// When per-vertex normals are given, they are interpolated across each face
+/// for smooth shading, otherwise the flat face normal is used.
+/// Per-vertex texture coordinates are interpolated the same way, faces of meshes
+/// without them get the barycentric coordinates of the hit point.
+/// The mesh keeps its own BVH over its triangles.
+#[derive(Debug, Clone)]
+pub struct TriangleMesh {
+    vertices: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<[f32; 2]>>,
+    indices: Vec<[usize; 3]>,
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(vertices: Vec<Vec3>, indices: Vec<[usize; 3]>) -> TriangleMesh {
+        TriangleMesh::build(vertices, None, indices)
+    }
+
+    /// `normals[i]` is the normal at `vertices[i]`.
+    pub fn with_normals(vertices: Vec<Vec3>, normals: Vec<Vec3>, indices: Vec<[usize; 3]>) -> TriangleMesh {
+        assert_eq!(vertices.len(), normals.len(), "there must be exactly one normal per vertex");
+        TriangleMesh::build(vertices, Some(normals), indices)
+    }
+
+    fn build(vertices: Vec<Vec3>, normals: Option<Vec<Vec3>>, indices: Vec<[usize; 3]>) -> TriangleMesh {
+        for face in &indices {
+            for &i in face {
+                assert!(i < vertices.len(), "vertex index {} out of bounds", i);
+            }
+        }
+
+        let mut mesh = TriangleMesh { vertices, normals, uvs: None, indices, bvh: Bvh::default() };
+        mesh.build_bvh();
+        mesh
+    }
+
+    /// `uvs[i]` is the texture coordinates of `vertices[i]`.
+    pub fn with_uvs(self, uvs: Vec<[f32; 2]>) -> TriangleMesh {
+        assert_eq!(self.vertices.len(), uvs.len(), "there must be exactly one uv per vertex");
+        TriangleMesh { uvs: Some(uvs), ..self }
+    }
+
+    fn build_bvh(&mut self) {
+        let bounds: Vec<Aabb> = (0..self.len()).map(|i| self.triangle(i).bounding_box()).collect();
+        self.bvh = Bvh::build(&bounds);
+    }
+
+    /// Computes smooth per-vertex normals by averaging the normals of the faces around each vertex,
+    /// weighted by their area.
+    pub fn smooth_normals(&mut self) {
+        let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); self.vertices.len()];
+
+        for &[a, b, c] in &self.indices {
+            // the length of the cross product is twice the area of the face
+            let weighted = (self.vertices[b] - self.vertices[a])
+                .cross_product(&(self.vertices[c] - self.vertices[a]));
+
+            normals[a] += weighted;
+            normals[b] += weighted;
+            normals[c] += weighted;
+        }
+
+        self.normals = Some(normals.iter().map(|normal| {
+            if normal.norm2() > 0.0 { normal.normalize() } else { *normal }
+        }).collect());
+    }
+
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.vertices
+    }
+
+    pub fn normals(&self) -> Option<&[Vec3]> {
+        self.normals.as_deref()
+    }
+
+    pub fn uvs(&self) -> Option<&[[f32; 2]]> {
+        self.uvs.as_deref()
+    }
+
+    pub fn indices(&self) -> &[[usize; 3]] {
+        &self.indices
+    }
+
+    /// Number of triangles.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn triangle(&self, i: usize) -> Triangle {
+        let [a, b, c] = self.indices[i];
+        Triangle::new(self.vertices[a], self.vertices[b], self.vertices[c])
+    }
+
+    fn intersect_face(&self, i: usize, ray: &Ray) -> Option<Intersection> {
+        let [a, b, c] = self.indices[i];
+        let (t, u, v) = intersect_triangle(ray, &self.vertices[a], &self.vertices[b], &self.vertices[c])?;
+
+        let normal = match &self.normals {
+            Some(normals) => ((1.0 - u - v) * normals[a] + u * normals[b] + v * normals[c]).normalize(),
+            None => face_normal(&self.vertices[a], &self.vertices[b], &self.vertices[c]),
+        };
+
+        let edge_1 = self.vertices[b] - self.vertices[a];
+        let edge_2 = self.vertices[c] - self.vertices[a];
+
+        let (uv, tangent) = match &self.uvs {
+            Some(uvs) => (
+                [0, 1].map(|k| (1.0 - u - v) * uvs[a][k] + u * uvs[b][k] + v * uvs[c][k]),
+                uv_tangent(&edge_1, &edge_2, uvs[a], uvs[b], uvs[c]).unwrap_or(edge_1),
+            ),
+            None => ([u, v], edge_1),
+        };
+
+        Some(Intersection {
+            point: ray.origin + t * ray.direction,
+            dist: t,
+            normal,
+            uv,
+            tangent: tangent.normalize(),
+        })
+    }
+}
+
+impl Shape for TriangleMesh {
+    fn translate(&mut self, d_pos: &Vec3) {
+        for vertex in self.vertices.iter_mut() {
+            *vertex += *d_pos;
+        }
+
+        self.build_bvh();
+    }
+
+    fn rotate_quat(&mut self, rotation: &Quat) {
+        let mat = rotation.to_mat3();
+        let center = self.bvh.bounds().center();
+
+        for vertex in self.vertices.iter_mut() {
+            *vertex = mat * (*vertex - center) + center;
+        }
+
+        if let Some(normals) = self.normals.as_mut() {
+            for normal in normals.iter_mut() {
+                *normal = mat * *normal;
+            }
+        }
+
+        self.build_bvh();
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        self.bvh.intersect(ray, |i| self.intersect_face(i, ray)).map(|(_, intersection)| intersection)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn unit_triangle() -> Triangle {
+        Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn test_triangle_intersection() {
+        let triangle = unit_triangle();
+
+        let ray = Ray::new(Vec3::new(0.25, 0.25, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let intersection = triangle.intersect(&ray).unwrap();
+        assert!((intersection.dist - 2.0).abs() < 1e-6);
+        assert!((intersection.point - Vec3::new(0.25, 0.25, 0.0)).norm() < 1e-6);
+        assert_eq!(intersection.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        let miss = Ray::new(Vec3::new(0.75, 0.75, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(triangle.intersect(&miss).is_none());
+
+        let behind = Ray::new(Vec3::new(0.25, 0.25, -2.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle.intersect(&behind).is_none());
+
+        let parallel = Ray::new(Vec3::new(-1.0, 0.25, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(triangle.intersect(&parallel).is_none());
+    }
+
+    #[test]
+    fn test_triangle_rotation() {
+        let mut triangle = unit_triangle();
+        let centroid = triangle.centroid();
+        triangle.rotate_quat(&Quat::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), std::f32::consts::PI));
+
+        // half a turn around the centroid
+        assert!((triangle.centroid() - centroid).norm() < 1e-6);
+        assert!((triangle.b - (2.0 * centroid - Vec3::new(1.0, 0.0, 0.0))).norm() < 1e-6);
+
+        let mut euler = unit_triangle();
+        euler.rotate(0.0, 0.0, std::f32::consts::PI);
+        assert!((euler.b - triangle.b).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_mesh_smooth_normals_are_interpolated() {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let normals = vec![
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        let mesh = TriangleMesh::with_normals(vertices, normals, vec![[0, 1, 2]]);
+
+        // barycentric weights: 0.4 for a, 0.4 for b, 0.2 for c
+        let ray = Ray::new(Vec3::new(0.4, 0.2, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let normal = mesh.intersect(&ray).unwrap().normal;
+        let expected = Vec3::new(0.4, 0.0, 0.6).normalize();
+        assert!((normal - expected).norm() < 1e-5, "got {:?}", normal);
+    }
+
+    #[test]
+    fn test_mesh_uvs_are_interpolated() {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let ray = Ray::new(Vec3::new(0.4, 0.2, -1.0), Vec3::new(0.0, 0.0, 1.0));
+
+        // without texture coordinates, the barycentric coordinates of b and c
+        let plain = TriangleMesh::new(vertices.clone(), vec![[0, 1, 2]]);
+        let [u, v] = plain.intersect(&ray).unwrap().uv;
+        assert!((u - 0.4).abs() < 1e-5 && (v - 0.2).abs() < 1e-5);
+
+        let textured = TriangleMesh::new(vertices, vec![[0, 1, 2]])
+            .with_uvs(vec![[0.5, 0.5], [1.0, 0.5], [0.5, 0.0]]);
+        let hit = textured.intersect(&ray).unwrap();
+        let [u, v] = hit.uv;
+        assert!((u - 0.7).abs() < 1e-5 && (v - 0.4).abs() < 1e-5, "got ({}, {})", u, v);
+
+        // u grows along x, v goes down along y
+        assert!((hit.tangent - Vec3::new(1.0, 0.0, 0.0)).norm() < 1e-5, "got {:?}", hit.tangent);
+    }
+
+    #[test]
+    fn test_smooth_normals_of_flat_mesh() {
+        let vertices = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let mut mesh = TriangleMesh::new(vertices, vec![[0, 1, 2], [0, 2, 3]]);
+        mesh.smooth_normals();
+
+        for normal in mesh.normals().unwrap() {
+            assert_eq!(*normal, Vec3::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn test_mesh_matches_triangles() {
+        let mut rng = rand::thread_rng();
+        let mut random_point = || Vec3::new(
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+        );
+
+        let vertices: Vec<Vec3> = (0..300).map(|_| random_point()).collect();
+        let indices: Vec<[usize; 3]> = (0..100).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+        let mesh = TriangleMesh::new(vertices, indices);
+
+        for _ in 0..200 {
+            let origin = Vec3::new(0.0, 0.0, -30.0);
+            let ray = Ray::new(origin, random_point() - origin);
+
+            let expected = (0..mesh.len())
+                .filter_map(|i| mesh.triangle(i).intersect(&ray))
+                .map(|intersection| intersection.dist)
+                .fold(None, |min: Option<f32>, dist| Some(min.map_or(dist, |min| min.min(dist))));
+
+            assert_eq!(mesh.intersect(&ray).map(|intersection| intersection.dist), expected);
+        }
+    }
+}
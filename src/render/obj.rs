@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::{FromStr, SplitWhitespace};
+use std::sync::Arc;
+
+use crate::math::Vec3;
+
+use super::material::{Material, Phong};
+use super::mesh::TriangleMesh;
+use super::objects::{Color, Scene};
+use super::texture::{ImageTexture, Texture};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Material as described in a MTL file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub emissive: Color,
+    pub shininess: f32,
+    pub optical_density: f32,
+    pub dissolve: f32,
+    pub illumination: u32,
+    pub diffuse_map: Option<String>,
+}
+
+impl MtlMaterial {
+    pub fn new(name: &str) -> MtlMaterial {
+        MtlMaterial {
+            name: name.to_string(),
+            ambient: Color::new(0.0, 0.0, 0.0),
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            emissive: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            optical_density: 1.0,
+            dissolve: 1.0,
+            illumination: 1,
+            diffuse_map: None,
+        }
+    }
+
+    pub fn to_material(&self) -> Phong {
+        Phong::new(self.diffuse)
+            .with_specular(self.specular, self.shininess)
+            .with_emissive(self.emissive)
+    }
+}
+
+/// Part of an OBJ file sharing the same group and material.
+#[derive(Debug, Clone)]
+pub struct ObjObject {
+    pub name: String,
+    pub material: Option<String>,
+    /// Has texture coordinates when every face of the object provides them.
+    pub mesh: TriangleMesh,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Obj {
+    pub objects: Vec<ObjObject>,
+    pub materials: Vec<MtlMaterial>,
+    /// Images referenced by the `diffuse_map` of the materials.
+    pub textures: HashMap<String, Arc<dyn Texture>>,
+}
+
+/// Tokens of the line being parsed along with where it comes from, for error reporting.
+struct Line<'a> {
+    file: &'a str,
+    number: usize,
+    tokens: SplitWhitespace<'a>,
+}
+
+impl<'a> Line<'a> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse { file: self.file.to_string(), line: self.number, message }
+    }
+
+    fn next<T: FromStr>(&mut self, what: &str) -> Result<T, ObjError> {
+        match self.tokens.next() {
+            Some(token) => token.parse().map_err(|_| self.error(format!("invalid {}: '{}'", what, token))),
+            None => Err(self.error(format!("missing {}", what))),
+        }
+    }
+
+    fn next_or<T: FromStr>(&mut self, what: &str, default: T) -> Result<T, ObjError> {
+        match self.tokens.next() {
+            Some(token) => token.parse().map_err(|_| self.error(format!("invalid {}: '{}'", what, token))),
+            None => Ok(default),
+        }
+    }
+
+    fn rest(&mut self, what: &str) -> Result<String, ObjError> {
+        let rest: Vec<&str> = self.tokens.by_ref().collect();
+
+        if rest.is_empty() {
+            Err(self.error(format!("missing {}", what)))
+        } else {
+            Ok(rest.join(" "))
+        }
+    }
+
+    fn vec3(&mut self, what: &str) -> Result<Vec3, ObjError> {
+        Ok(Vec3::new(self.next(what)?, self.next(what)?, self.next(what)?))
+    }
+
+    fn color(&mut self, what: &str) -> Result<Color, ObjError> {
+        let r = self.next(what)?;
+        // a single value means a grey level
+        let g = self.next_or(what, r)?;
+        let b = self.next_or(what, r)?;
+        Ok(Color::new(r, g, b))
+    }
+}
+
+/// Iterates over the non-empty, non-comment lines of `source`.
+fn lines<'a>(file: &'a str, source: &'a str) -> impl Iterator<Item = (&'a str, Line<'a>)> {
+    source.lines().enumerate().filter_map(move |(i, line)| {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next()?;
+        Some((keyword, Line { file, number: i + 1, tokens }))
+    })
+}
+
+pub fn parse_mtl(file: &str, source: &str) -> Result<Vec<MtlMaterial>, ObjError> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+
+    for (keyword, mut line) in lines(file, source) {
+        if keyword == "newmtl" {
+            materials.push(MtlMaterial::new(&line.rest("material name")?));
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => return Err(line.error(format!("'{}' before any 'newmtl'", keyword))),
+        };
+
+        match keyword {
+            "Ka" => material.ambient = line.color("ambient color")?,
+            "Kd" => material.diffuse = line.color("diffuse color")?,
+            "Ks" => material.specular = line.color("specular color")?,
+            "Ke" => material.emissive = line.color("emissive color")?,
+            "Ns" => material.shininess = line.next("shininess")?,
+            "Ni" => material.optical_density = line.next("optical density")?,
+            "d" => material.dissolve = line.next("dissolve")?,
+            "Tr" => material.dissolve = 1.0 - line.next::<f32>("transparency")?,
+            "illum" => material.illumination = line.next("illumination model")?,
+            "map_Kd" => material.diffuse_map = Some(line.rest("texture path")?),
+            // other statements (texture options, other maps...) are not supported and ignored
+            _ => {},
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Vertex of a face: indices of its position, texture coordinates and normal.
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct ObjectBuilder {
+    name: String,
+    material: Option<String>,
+    faces: Vec<[FaceVertex; 3]>,
+}
+
+impl ObjectBuilder {
+    fn build(self, positions: &[Vec3], uvs: &[[f32; 2]], normals: &[Vec3]) -> ObjObject {
+        let has_uvs = self.faces.iter().flatten().all(|(_, uv, _)| uv.is_some());
+        let has_normals = self.faces.iter().flatten().all(|(_, _, normal)| normal.is_some());
+
+        // OBJ indexes positions, uvs and normals separately, meshes need a single index per vertex
+        let mut vertices: HashMap<FaceVertex, usize> = HashMap::new();
+        let mut mesh_positions = Vec::new();
+        let mut mesh_uvs = Vec::new();
+        let mut mesh_normals = Vec::new();
+
+        let indices = self.faces.iter().map(|face| {
+            face.map(|(position, uv, normal)| {
+                let uv = if has_uvs { uv } else { None };
+                let normal = if has_normals { normal } else { None };
+
+                *vertices.entry((position, uv, normal)).or_insert_with(|| {
+                    mesh_positions.push(positions[position]);
+                    if let Some(uv) = uv {
+                        mesh_uvs.push(uvs[uv]);
+                    }
+                    if let Some(normal) = normal {
+                        mesh_normals.push(normals[normal]);
+                    }
+                    mesh_positions.len() - 1
+                })
+            })
+        }).collect();
+
+        let mut mesh = if has_normals {
+            TriangleMesh::with_normals(mesh_positions, mesh_normals, indices)
+        } else {
+            TriangleMesh::new(mesh_positions, indices)
+        };
+
+        if has_uvs {
+            mesh = mesh.with_uvs(mesh_uvs);
+        }
+
+        ObjObject {
+            name: self.name,
+            material: self.material,
+            mesh,
+        }
+    }
+}
+
+/// Turns a 1-based, possibly negative (relative to the end), OBJ index into a 0-based one.
+fn resolve_index(line: &Line, token: &str, what: &str, count: usize) -> Result<usize, ObjError> {
+    let index: i64 = token.parse()
+        .map_err(|_| line.error(format!("invalid {} index: '{}'", what, token)))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(line.error(format!("{} index {} out of range ({} defined)", what, index, count)));
+    }
+
+    Ok(resolved as usize)
+}
+
+impl Obj {
+    /// Loads an OBJ file along with the MTL files it references, looked up relative to it,
+    /// and the texture images of the materials, looked up relative to their MTL file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Obj, ObjError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|error| ObjError::Io(path.to_path_buf(), error))?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut obj = Obj::parse(&path.display().to_string(), &source, |name| {
+            let mtl_path = directory.join(name);
+            let source = fs::read_to_string(&mtl_path).map_err(|error| ObjError::Io(mtl_path.clone(), error))?;
+            let mut materials = parse_mtl(&mtl_path.display().to_string(), &source)?;
+
+            let mtl_directory = mtl_path.parent().unwrap_or(Path::new(""));
+            for map in materials.iter_mut().filter_map(|material| material.diffuse_map.as_mut()) {
+                *map = mtl_directory.join(&*map).display().to_string();
+            }
+
+            Ok(materials)
+        })?;
+
+        for map in obj.materials.iter().filter_map(|material| material.diffuse_map.as_ref()) {
+            if !obj.textures.contains_key(map) {
+                let texture = ImageTexture::load(map).map_err(|error| ObjError::Io(PathBuf::from(map), error))?;
+                obj.textures.insert(map.clone(), Arc::new(texture));
+            }
+        }
+
+        Ok(obj)
+    }
+
+    /// Parses the content of an OBJ file, `load_mtl` is called for every `mtllib` statement.
+    /// `file` is only used in error messages.
+    pub fn parse<F>(file: &str, source: &str, mut load_mtl: F) -> Result<Obj, ObjError>
+    where
+        F: FnMut(&str) -> Result<Vec<MtlMaterial>, ObjError>,
+    {
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut uvs: Vec<[f32; 2]> = Vec::new();
+        let mut normals: Vec<Vec3> = Vec::new();
+
+        let mut obj = Obj::default();
+        let mut builders: Vec<ObjectBuilder> = Vec::new();
+        let mut current = ObjectBuilder::default();
+
+        for (keyword, mut line) in lines(file, source) {
+            match keyword {
+                "v" => positions.push(line.vec3("vertex coordinate")?),
+                "vt" => uvs.push([line.next("texture coordinate")?, line.next_or("texture coordinate", 0.0)?]),
+                "vn" => normals.push(line.vec3("normal coordinate")?.normalize()),
+                "f" => {
+                    let mut vertices: Vec<FaceVertex> = Vec::new();
+
+                    while let Some(token) = line.tokens.next() {
+                        let mut parts = token.split('/');
+                        let position = resolve_index(&line, parts.next().unwrap_or(""), "vertex", positions.len())?;
+                        let uv = match parts.next() {
+                            Some("") | None => None,
+                            Some(part) => Some(resolve_index(&line, part, "texture coordinate", uvs.len())?),
+                        };
+                        let normal = match parts.next() {
+                            Some("") | None => None,
+                            Some(part) => Some(resolve_index(&line, part, "normal", normals.len())?),
+                        };
+                        vertices.push((position, uv, normal));
+                    }
+
+                    if vertices.len() < 3 {
+                        return Err(line.error(format!("face with {} vertices", vertices.len())));
+                    }
+
+                    // polygons are triangulated as a fan around their first vertex
+                    for i in 1..vertices.len() - 1 {
+                        current.faces.push([vertices[0], vertices[i], vertices[i + 1]]);
+                    }
+                },
+                "o" | "g" | "usemtl" => {
+                    let value = if keyword == "g" {
+                        line.rest("group name").unwrap_or_default()
+                    } else {
+                        line.rest(if keyword == "o" { "object name" } else { "material name" })?
+                    };
+
+                    if keyword == "usemtl" && !obj.materials.iter().any(|material| material.name == value) {
+                        return Err(line.error(format!("unknown material '{}'", value)));
+                    }
+
+                    let mut next = ObjectBuilder {
+                        name: current.name.clone(),
+                        material: current.material.clone(),
+                        faces: Vec::new(),
+                    };
+
+                    if keyword == "usemtl" {
+                        next.material = Some(value);
+                    } else {
+                        next.name = value;
+                    }
+
+                    builders.push(std::mem::replace(&mut current, next));
+                },
+                "mtllib" => {
+                    let names = line.rest("material library")?;
+                    for name in names.split_whitespace() {
+                        obj.materials.extend(load_mtl(name)?);
+                    }
+                },
+                // smoothing groups, lines, points... are not supported and ignored
+                _ => {},
+            }
+        }
+
+        builders.push(current);
+
+        obj.objects = builders.into_iter()
+            .filter(|builder| !builder.faces.is_empty())
+            .map(|builder| builder.build(&positions, &uvs, &normals))
+            .collect();
+
+        Ok(obj)
+    }
+
+    pub fn material(&self, name: &str) -> Option<&MtlMaterial> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+
+    /// Adds every object of the file to `scene`, along with its material.
+    pub fn add_to(self, scene: &mut Scene) {
+        let materials: HashMap<&str, Arc<dyn Material>> = self.materials.iter()
+            .map(|material| {
+                let mut phong = material.to_material();
+
+                if let Some(texture) = material.diffuse_map.as_ref().and_then(|map| self.textures.get(map)) {
+                    phong = phong.with_texture(texture.clone());
+                }
+
+                (material.name.as_str(), Arc::new(phong) as Arc<dyn Material>)
+            })
+            .collect();
+
+        for object in self.objects {
+            let material = object.material.as_deref().and_then(|name| materials.get(name));
+
+            match material {
+                Some(material) => scene.add_object_with_material(Box::new(object.mesh), material.clone()),
+                None => scene.add_object(Box::new(object.mesh)),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::objects::{Camera, Diamond, Ray};
+    use crate::render::texture::Checker;
+
+    fn no_mtl(name: &str) -> Result<Vec<MtlMaterial>, ObjError> {
+        panic!("unexpected material library {}", name)
+    }
+
+    #[test]
+    fn test_parse_quad() {
+        let source = "
+            # a unit square made of one polygon
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            f 1 2 3 4
+        ";
+
+        let obj = Obj::parse("quad.obj", source, no_mtl).unwrap();
+        assert_eq!(obj.objects.len(), 1);
+
+        let mesh = &obj.objects[0].mesh;
+        assert_eq!(mesh.indices(), &[[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.vertices().len(), 4);
+        assert!(mesh.normals().is_none());
+        assert!(mesh.uvs().is_none());
+    }
+
+    #[test]
+    fn test_parse_negative_indices_normals_and_uvs() {
+        let source = "
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 0 1
+            vn 0 0 2
+            f -3/-3/-1 -2/-2/-1 -1/-1/-1
+        ";
+
+        let obj = Obj::parse("triangle.obj", source, no_mtl).unwrap();
+        let object = &obj.objects[0];
+
+        assert_eq!(object.mesh.indices(), &[[0, 1, 2]]);
+        assert_eq!(object.mesh.normals().unwrap(), &[Vec3::new(0.0, 0.0, 1.0); 3]);
+        assert_eq!(object.mesh.uvs().unwrap(), &[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+    }
+
+    #[test]
+    fn test_parse_groups_and_materials() {
+        let source = "
+            mtllib scene.mtl
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            v 1 1 0
+            g first
+            usemtl red
+            f 1 2 3
+            g second
+            f 2 4 3
+            usemtl blue
+            f 1//1 2//1 4//1
+            vn 0 0 1
+        ";
+
+        let mtl = "
+            newmtl red
+            Kd 1 0 0
+            Ns 10
+            newmtl blue
+            Kd 0 0 1
+            map_Kd textures/blue.png
+        ";
+
+        let result = Obj::parse("scene.obj", source, |name| {
+            assert_eq!(name, "scene.mtl");
+            parse_mtl(name, mtl)
+        });
+
+        // the normal is used before being defined
+        match result {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 13),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let source = source.replace("f 1//1 2//1 4//1\n", "f 1 2 4\n");
+        let obj = Obj::parse("scene.obj", &source, |name| parse_mtl(name, mtl)).unwrap();
+
+        let summary: Vec<(&str, Option<&str>, usize)> = obj.objects.iter()
+            .map(|object| (object.name.as_str(), object.material.as_deref(), object.mesh.len()))
+            .collect();
+
+        assert_eq!(summary, vec![
+            ("first", Some("red"), 1),
+            ("second", Some("red"), 1),
+            ("second", Some("blue"), 1),
+        ]);
+
+        let blue = obj.material("blue").unwrap();
+        assert_eq!(blue.diffuse, Color::new(0.0, 0.0, 1.0));
+        assert_eq!(blue.diffuse_map.as_deref(), Some("textures/blue.png"));
+        assert_eq!(obj.material("red").unwrap().shininess, 10.0);
+    }
+
+    #[test]
+    fn test_textured_material() {
+        let mtl = "newmtl checker\nKd 1 0.5 1\nmap_Kd checker.png\n";
+        let source = "
+            mtllib scene.mtl
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 0 1
+            usemtl checker
+            f 1/1 2/2 3/3
+        ";
+
+        let mut obj = Obj::parse("scene.obj", source, |name| parse_mtl(name, mtl)).unwrap();
+        let checker = Checker::new(Color::new(1.0, 1.0, 1.0), Color::black(), 2.0);
+        obj.textures.insert("checker.png".to_string(), Arc::new(checker));
+
+        let mut scene = Scene::new(Camera::new(Vec3::new(0.0, 0.0, -1.0), Diamond::default()));
+        obj.add_to(&mut scene);
+
+        let albedo_at = |x: f32, y: f32| {
+            let ray = Ray::new(Vec3::new(x, y, -1.0), Vec3::new(0.0, 0.0, 1.0));
+            let hit = scene.shapes[0].intersect(&ray).unwrap();
+            scene.material(0).albedo(&hit)
+        };
+
+        assert_eq!(albedo_at(0.1, 0.1), Color::new(1.0, 0.5, 1.0));
+        assert!(albedo_at(0.6, 0.1).is_black());
+    }
+
+    #[test]
+    fn test_parse_errors_have_line_numbers() {
+        let cases = [
+            ("v 0 0 0\nv 1 zero 0\n", 2, "invalid vertex coordinate: 'zero'"),
+            ("v 0 0 0\nv 1 0 0\nf 1 2\n", 3, "face with 2 vertices"),
+            ("v 0 0 0\n\nf 1 1 5\n", 3, "vertex index 5 out of range (1 defined)"),
+            ("v 0 0 0\nf 0 1 1\n", 2, "vertex index 0 out of range (1 defined)"),
+            ("usemtl missing\n", 1, "unknown material 'missing'"),
+        ];
+
+        for (source, expected_line, expected_message) in cases {
+            match Obj::parse("broken.obj", source, no_mtl) {
+                Err(ObjError::Parse { file, line, message }) => {
+                    assert_eq!(file, "broken.obj");
+                    assert_eq!(line, expected_line, "{}", source);
+                    assert_eq!(message, expected_message);
+                },
+                other => panic!("expected a parse error for {:?}, got {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_mtl_errors() {
+        let error = parse_mtl("broken.mtl", "Kd 1 1 1\n").unwrap_err();
+        assert_eq!(error.to_string(), "broken.mtl:1: 'Kd' before any 'newmtl'");
+    }
+}
@@ -1,20 +1,59 @@
-use crate::math::{Vec3, Mat3};
+use std::f32::consts::PI;
+use std::ops;
+use std::sync::Arc;
+
+use crate::math::{Vec3, Mat3, Quat};
+
+use super::bvh::{Aabb, Bvh};
+use super::environment::Environment;
+pub use super::camera::Camera;
+pub use super::light::Light;
+use super::material::{Material, Phong};
+use super::sampling;
 
 pub trait Shape: Send + Sync {
     fn translate(&mut self, d_pos: &Vec3);
-    fn rotate(&mut self, theta_x: f32, theta_y: f32, theta_z: f32);
+
+    /// Euler angles rotation, same as `Mat3::rot_x_y_z`.
+    fn rotate(&mut self, theta_x: f32, theta_y: f32, theta_z: f32) {
+        self.rotate_quat(&Quat::from_euler(theta_x, theta_y, theta_z));
+    }
+
+    fn rotate_quat(&mut self, rotation: &Quat);
     fn intersect(&self, ray: &Ray) -> Option<Intersection>;
+    fn bounding_box(&self) -> Aabb;
+
+    /// Stretches of the whole line of `ray`, behind its origin too, lying inside the shape,
+    /// sorted along the ray, with normals pointing out of the shape. They let shapes be combined
+    /// by constructive solid geometry.
+    /// Shapes not enclosing a volume, such as flat ones, have none.
+    fn intervals(&self, _ray: &Ray) -> Vec<Interval> {
+        Vec::new()
+    }
 }
 
-#[derive(Debug)]
+/// Stretch of a ray inside a solid, from the point where it enters it to the one where it leaves it.
+#[derive(Debug, Clone, Copy)]
+pub struct Interval {
+    pub enter: Intersection,
+    pub exit: Intersection,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// Instant at which the ray is traced, while the shutter of the camera is open.
+    pub time: f32,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction: direction.normalize() }
+        Ray { origin, direction: direction.normalize(), time: 0.0 }
+    }
+
+    pub fn at_time(self, time: f32) -> Ray {
+        Ray { time, ..self }
     }
 }
 
@@ -59,15 +98,9 @@ impl Quad {
             depth,
         }
     }
-}
-
-impl Shape for Quad {
-    fn translate(&mut self, d_pos: &Vec3) {
-        self.center = self.center + *d_pos;
-    }
 
-    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-        let mut intersections = [
+    fn faces(&self) -> [Diamond; 6] {
+        [
             Diamond::new(self.center - self.width / 2.0, self.height, self.depth),
             Diamond::new(self.center + self.width / 2.0, self.height, self.depth),
             Diamond::new(self.center - self.height / 2.0, self.width, self.depth),
@@ -75,20 +108,71 @@ impl Shape for Quad {
             Diamond::new(self.center - self.depth / 2.0, self.width, self.height),
             Diamond::new(self.center + self.depth / 2.0, self.width, self.height),
         ]
+    }
+
+    /// `intersection` with a face, its normal made to point outwards.
+    fn outwards(&self, intersection: Intersection) -> Intersection {
+        if intersection.normal.dot(&(intersection.point - self.center)) < 0.0 {
+            intersection.flipped()
+        } else {
+            intersection
+        }
+    }
+}
+
+impl Shape for Quad {
+    fn translate(&mut self, d_pos: &Vec3) {
+        self.center = self.center + *d_pos;
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let mut intersections = self.faces()
             .map(|diamond| diamond.intersect(ray))
             .iter()
             .filter(|i| i.is_some())
             .map(|i| i.unwrap())
             .collect::<Vec<_>>();
 
-        Intersection::nearest(&mut intersections)
+        Intersection::nearest(&mut intersections).map(|intersection| self.outwards(intersection))
     }
 
-    fn rotate(&mut self, theta_x: f32, theta_y: f32, theta_z: f32) {
-        let mat = Mat3::rot_x_y_z(theta_x, theta_y, theta_z);
-        self.width = mat * (self.width - self.center) + self.center;
-        self.height = mat * (self.height - self.center) + self.center;
-        self.depth = mat * (self.depth - self.center) + self.center;
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        // starting from behind the box, every face crossed by the line is in front of the ray
+        let back = (ray.origin - self.center).norm() + self.width.norm() + self.height.norm() + self.depth.norm();
+        let behind = Ray { origin: ray.origin - back * ray.direction, ..*ray };
+
+        let mut intersections: Vec<Intersection> = self.faces().iter()
+            .filter_map(|face| face.intersect(&behind))
+            .map(|intersection| Intersection { dist: intersection.dist - back, ..self.outwards(intersection) })
+            .collect();
+        intersections.sort_by(|a, b| a.dist.partial_cmp(&b.dist).unwrap());
+
+        // convex: in through the first face, out through the last one
+        match (intersections.first(), intersections.last()) {
+            (Some(&enter), Some(&exit)) if exit.dist > enter.dist => vec![Interval { enter, exit }],
+            _ => Vec::new(),
+        }
+    }
+
+    fn rotate_quat(&mut self, rotation: &Quat) {
+        let mat = rotation.to_mat3();
+        self.width = mat * self.width;
+        self.height = mat * self.height;
+        self.depth = mat * self.depth;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let mut corners = Vec::with_capacity(8);
+
+        for w in [-0.5, 0.5] {
+            for h in [-0.5, 0.5] {
+                for d in [-0.5, 0.5] {
+                    corners.push(self.center + w * self.width + h * self.height + d * self.depth);
+                }
+            }
+        }
+
+        Aabb::from_points(&corners)
     }
 }
 
@@ -127,31 +211,92 @@ impl Shape for Sphere {
             ];
 
             let min = if candidates[0] < candidates[1] { candidates[0] } else { candidates[1] };
+            let max = if candidates[0] < candidates[1] { candidates[1] } else { candidates[0] };
+
+            // the ray may start inside the sphere, in which case only the far side is in front of it
+            let min = if min >= 0.0 { min } else if max >= 0.0 { max } else { return None };
 
             let point = ray.origin + min * ray.direction;
+            let normal = (point - self.center).normalize();
 
             Some(Intersection {
                 point,
                 dist: min,
-                normal: (point - self.center).normalize(),
+                normal,
+                uv: sphere_uv(&normal),
+                tangent: sphere_tangent(&normal),
             })
-        } else if delta == 0.0 {
+        } else if delta == 0.0 && -b / (2.0 * a) >= 0.0 {
             let t = -b / (2.0 * a);
             let point = ray.origin + t * ray.direction;
+            let normal = (point - self.center).normalize();
 
             Some(Intersection {
                 point,
                 dist: t,
-                normal: (point - self.center).normalize(),
+                normal,
+                uv: sphere_uv(&normal),
+                tangent: sphere_tangent(&normal),
             })
         } else {
             None
         }
     }
 
-    fn rotate(&mut self, _theta_x: f32, _theta_y: f32, _theta_z: f32) {
+    fn rotate_quat(&mut self, _rotation: &Quat) {
         // nothing to do fow now as spheres are homogeneous
     }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let delta_o = ray.origin - self.center;
+        let v = ray.direction;
+
+        let a = v.norm2();
+        let b = 2.0 * delta_o.dot(&v);
+        let c = delta_o.norm2() - self.radius * self.radius;
+
+        let delta = b * b - 4.0 * a * c;
+        if delta <= 0.0 {
+            return Vec::new();
+        }
+
+        let hit = |t: f32| {
+            let point = ray.origin + t * ray.direction;
+            let normal = (point - self.center).normalize();
+            Intersection { point, dist: t, normal, uv: sphere_uv(&normal), tangent: sphere_tangent(&normal) }
+        };
+
+        vec![Interval {
+            enter: hit((-b - delta.sqrt()) / (2.0 * a)),
+            exit: hit((-b + delta.sqrt()) / (2.0 * a)),
+        }]
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - r, self.center + r)
+    }
+}
+
+/// Longitude and latitude of the point of a sphere with the given normal, mapped to [0, 1],
+/// the poles being along the y axis.
+fn sphere_uv(normal: &Vec3) -> [f32; 2] {
+    // longitudes go counter-clockwise seen from outside, for u, v and the normal to be right-handed
+    let u = 0.5 + (-normal.z).atan2(normal.x) / (2.0 * PI);
+    let v = 0.5 + normal.y.clamp(-1.0, 1.0).asin() / PI;
+
+    [u, v]
+}
+
+/// Direction of increasing longitude, along the parallel, any direction at the poles.
+fn sphere_tangent(normal: &Vec3) -> Vec3 {
+    let tangent = Vec3::new(normal.z, 0.0, -normal.x);
+
+    if tangent.norm2() > 1e-12 {
+        tangent.normalize()
+    } else {
+        sampling::orthonormal_basis(normal).0
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -159,9 +304,39 @@ pub struct Intersection {
     pub point: Vec3,
     pub dist: f32,
     pub normal: Vec3,
+    /// Texture coordinates of the point on the surface of the shape.
+    pub uv: [f32; 2],
+    /// Unit direction of increasing u on the surface. The direction of increasing v
+    /// is roughly `normal x tangent`, shapes keep their texture coordinates right-handed.
+    pub tangent: Vec3,
 }
 
 impl Intersection {
+    /// Tangent and bitangent (direction of increasing v) made orthonormal with the normal,
+    /// the frame normal maps are expressed in.
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        let tangent = self.tangent - self.tangent.dot(&self.normal) * self.normal;
+
+        let tangent = if tangent.norm2() > 1e-12 {
+            tangent.normalize()
+        } else {
+            sampling::orthonormal_basis(&self.normal).0
+        };
+
+        (tangent, self.normal.cross_product(&tangent))
+    }
+
+    /// Same point seen from the other side of the surface, the texture being mirrored
+    /// to keep it right-handed.
+    pub fn flipped(&self) -> Intersection {
+        Intersection {
+            normal: -self.normal,
+            uv: [1.0 - self.uv[0], self.uv[1]],
+            tangent: -self.tangent,
+            ..*self
+        }
+    }
+
     pub fn nearest(intersections: &mut[Intersection]) -> Option<Intersection> {
         if intersections.is_empty() {
             None
@@ -196,18 +371,16 @@ impl Shape for Diamond {
             let t = -neg_t;
 
             if w >= -0.5 && w <= 0.5 && h >= -0.5 && h <= 0.5 && t >= 0.0  {
-                let cross_products = self.width.cross(&self.height);
-
-                let normal = if cross_products[0].dot(&ray.direction) > 0.0 {
-                    cross_products[0]
-                } else {
-                    cross_products[1]
-                };
+                // width x height, whichever side the ray comes from
+                let normal = self.width.cross(&self.height)[0];
 
                 Some(Intersection {
                     point: ray.origin + t * ray.direction,
                     dist: t,
                     normal,
+                    // (0, 0) at the center - width / 2 - height / 2 corner
+                    uv: [w + 0.5, h + 0.5],
+                    tangent: self.width.normalize(),
                 })
             } else {
                 None
@@ -217,14 +390,26 @@ impl Shape for Diamond {
         }
     }
 
-    fn rotate(&mut self, theta_x: f32, theta_y: f32, theta_z: f32) {
-        let mat = Mat3::rot_x_y_z(theta_x, theta_y, theta_z);
-        self.width = mat * (self.width - self.center) + self.center;
-        self.height = mat * (self.height - self.center) + self.center;
+    fn rotate_quat(&mut self, rotation: &Quat) {
+        let mat = rotation.to_mat3();
+        self.width = mat * self.width;
+        self.height = mat * self.height;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let w = self.width / 2.0;
+        let h = self.height / 2.0;
+
+        Aabb::from_points(&[
+            self.center - w - h,
+            self.center - w + h,
+            self.center + w - h,
+            self.center + w + h,
+        ])
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub rgb: Vec3,
 }
@@ -234,62 +419,56 @@ impl Color {
         Color { rgb: Vec3::new(r, g, b) }
     }
 
-    pub fn dim(&self, f: f32) -> Color {
-        let rgb = self.rgb / f;
+    pub fn black() -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
 
-        Color { rgb }
+    pub fn is_black(&self) -> bool {
+        self.rgb.x <= 0.0 && self.rgb.y <= 0.0 && self.rgb.z <= 0.0
     }
-}
 
-#[derive(Debug)]
-pub struct Light {
-    sphere: Sphere,
-    pub color: Color,
+    /// Relative luminance, how bright the linear colour looks.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.rgb.x + 0.7152 * self.rgb.y + 0.0722 * self.rgb.z
+    }
 }
 
-impl Light {
-    pub fn new(center: Vec3, radius: f32, color: Color) -> Light {
-        Light { sphere: Sphere::new(center, radius), color }
-    }
+impl ops::Add<Color> for Color {
+    type Output = Color;
 
-    pub fn origin(&self) -> Vec3 {
-        self.sphere.center
+    fn add(self, other: Color) -> Color {
+        Color { rgb: self.rgb + other.rgb }
     }
 }
 
-impl Shape for Light {
-    fn translate(&mut self, d_pos: &Vec3) {
-        self.sphere.translate(d_pos);
+impl ops::AddAssign<Color> for Color {
+    fn add_assign(&mut self, other: Color) {
+        self.rgb += other.rgb;
     }
+}
 
-    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-        self.sphere.intersect(ray)
-    }
+impl ops::Sub<Color> for Color {
+    type Output = Color;
 
-    fn rotate(&mut self, theta_x: f32, theta_y: f32, theta_z: f32) {
-        self.sphere.rotate(theta_x, theta_y, theta_z);
+    fn sub(self, other: Color) -> Color {
+        Color { rgb: self.rgb - other.rgb }
     }
 }
 
-#[derive(Debug)]
-pub struct Camera {
-    pub position: Vec3,
-    pub screen: Diamond,
-}
+/// Component-wise product, e.g. light filtered by a surface.
+impl ops::Mul<Color> for Color {
+    type Output = Color;
 
-impl Camera {
-    pub fn new(position: Vec3, screen: Diamond) -> Camera {
-        Camera { position, screen }
+    fn mul(self, other: Color) -> Color {
+        Color::new(self.rgb.x * other.rgb.x, self.rgb.y * other.rgb.y, self.rgb.z * other.rgb.z)
     }
+}
 
-    pub fn rotate(&mut self, theta_x: f32, theta_y: f32, theta_z: f32) {
-        self.screen.rotate(theta_x, theta_y, theta_z);
-    }
+impl ops::Mul<f32> for Color {
+    type Output = Color;
 
-    pub fn translate(&mut self, d_pos_x: f32, d_pos_y: f32, d_pos_z: f32) {
-        let d_pos = Vec3::new(d_pos_x, d_pos_y, d_pos_z);
-        self.position += d_pos;
-        self.screen.translate(&d_pos);
+    fn mul(self, rhs: f32) -> Color {
+        Color { rgb: rhs * self.rgb }
     }
 }
 
@@ -297,6 +476,14 @@ pub struct Scene {
     pub camera: Camera,
     pub shapes: Vec<Box<dyn Shape>>,
     pub lights: Vec<Light>,
+    /// Light surrounding the scene, seen by the reflected and refracted rays leaving it
+    /// and lighting the surfaces like an area light. Black by default.
+    pub environment: Environment,
+    /// Whether camera rays leaving the scene see the environment too,
+    /// rather than leaving the pixel transparent. Off by default.
+    pub backdrop: bool,
+    materials: Vec<Arc<dyn Material>>,
+    bvh: Option<Bvh>,
 }
 
 impl Scene {
@@ -305,16 +492,75 @@ impl Scene {
             camera,
             shapes: Vec::new(),
             lights: Vec::new(),
+            environment: Environment::default(),
+            backdrop: false,
+            materials: Vec::new(),
+            bvh: None,
         }
     }
 
+    /// Adds a shape with the default, plain white, material.
     pub fn add_object(&mut self, object: Box<dyn Shape>) -> &mut Self {
+        self.add_object_with_material(object, Arc::new(Phong::default()))
+    }
+
+    pub fn add_object_with_material(&mut self, object: Box<dyn Shape>, material: Arc<dyn Material>) -> &mut Self {
         self.shapes.push(object);
+        self.materials.push(material);
+        self.bvh = None;
         self
     }
 
+    /// Material of the shape at `index` in `shapes`.
+    pub fn material(&self, index: usize) -> &dyn Material {
+        self.materials[index].as_ref()
+    }
+
+    pub fn set_material(&mut self, index: usize, material: Arc<dyn Material>) {
+        self.materials[index] = material;
+    }
+
     pub fn add_light(&mut self, light: Light) -> &mut Self {
         self.lights.push(light);
         self
     }
+
+    /// (Re)builds the acceleration structure over `shapes`.
+    /// Needs to be called again after moving shapes around through `shapes`.
+    pub fn build_bvh(&mut self) {
+        let bounds: Vec<Aabb> = self.shapes.iter().map(|shape| shape.bounding_box()).collect();
+        self.bvh = Some(Bvh::build(&bounds));
+    }
+
+    /// Builds the acceleration structure unless it is already up to date with `add_object`.
+    pub fn update_bvh(&mut self) {
+        if self.bvh.is_none() {
+            self.build_bvh();
+        }
+    }
+
+    /// Nearest shape hit by `ray` along with its index in `shapes`.
+    /// Falls back to testing every shape when the BVH hasn't been built.
+    pub fn intersect(&self, ray: &Ray) -> Option<(usize, Intersection)> {
+        match &self.bvh {
+            Some(bvh) => bvh.intersect(ray, |i| self.shapes[i].intersect(ray)),
+            None => self.shapes.iter()
+                .enumerate()
+                .filter_map(|(i, shape)| shape.intersect(ray).map(|intersection| (i, intersection)))
+                .min_by(|(_, a), (_, b)| a.dist.partial_cmp(&b.dist).unwrap()),
+        }
+    }
+
+    /// Whether something lies on `ray` between the distances `t_min` and `t_max`.
+    pub fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        let hit = |shape: &dyn Shape| match shape.intersect(ray) {
+            Some(intersection) => intersection.dist > t_min && intersection.dist < t_max,
+            None => false,
+        };
+
+        match &self.bvh {
+            Some(bvh) => bvh.any(ray, t_max, |i| hit(self.shapes[i].as_ref())),
+            None => self.shapes.iter().any(|shape| hit(shape.as_ref())),
+        }
+    }
 }
@@ -0,0 +1,107 @@
+use rand::{Rng, RngCore};
+
+use super::material::Specular;
+use super::objects::{Color, Ray, Scene};
+use super::sampling::power_heuristic;
+use super::{backdrop, environment_contribution, facing_hit, light_contribution, material, RenderSettings, SECONDARY_RAY_EPSILON};
+
+/// Bounces after which paths may be terminated by russian roulette.
+const ROULETTE_DEPTH: u32 = 3;
+
+/// Estimates the light arriving along `ray` by following one random path through the scene,
+/// `None` when the ray doesn't hit anything and there is no backdrop.
+///
+/// Direct lighting is computed at every diffuse bounce by sampling the lights (next event
+/// estimation), the path is then continued in a direction picked by the material.
+/// The environment can be reached both ways, which are weighted by multiple importance sampling.
+pub fn trace(ray: &Ray, scene: &Scene, settings: &RenderSettings, rng: &mut dyn RngCore) -> Option<Color> {
+    let (mut index, mut intersection) = match scene.intersect(ray) {
+        Some(hit) => hit,
+        None => return backdrop(ray, scene),
+    };
+    let mut ray = *ray;
+
+    let mut radiance = Color::black();
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+
+    for depth in 0..=settings.max_depth {
+        let material = scene.material(index);
+
+        let (hit, outside) = facing_hit(&ray, material, &intersection);
+        let normal = hit.normal;
+        let view_dir = -ray.direction;
+
+        // point lights can't be hit by rays, so emission and next event estimation never overlap
+        radiance += throughput * material.emitted(&hit);
+
+        if depth == settings.max_depth {
+            break;
+        }
+
+        // density of the direction picked by a diffuse bounce, none for a specular one
+        let (direction, weight, pdf) = match material.specular() {
+            Some(Specular::Mirror(tint)) => (ray.direction.reflect(&normal), tint, None),
+            Some(Specular::Dielectric { ior, tint }) => {
+                let eta = if outside { 1.0 / ior } else { ior };
+                let reflectance = material::schlick(view_dir.dot(&normal), eta);
+
+                // follow either the reflected or the refracted ray, in proportion to the Fresnel term
+                match ray.direction.refract(&normal, eta) {
+                    Some(refracted) if rng.gen::<f32>() >= reflectance => (refracted, tint, None),
+                    _ => (ray.direction.reflect(&normal), Color::new(1.0, 1.0, 1.0), None),
+                }
+            },
+            None => {
+                for light in &scene.lights {
+                    if let Some(color) = light_contribution(scene, settings, light, material, &hit, &ray, rng) {
+                        radiance += throughput * color;
+                    }
+                }
+
+                if let Some(color) = environment_contribution(scene, settings, material, &hit, &ray, rng, true) {
+                    radiance += throughput * color;
+                }
+
+                match material.sample(&hit, &view_dir, rng) {
+                    Some((direction, weight)) => (direction, weight, Some(material.pdf(&hit, &view_dir, &direction))),
+                    None => break,
+                }
+            },
+        };
+
+        throughput = throughput * weight;
+
+        if depth >= ROULETTE_DEPTH {
+            let survival = throughput.rgb.x.max(throughput.rgb.y).max(throughput.rgb.z).clamp(0.05, 0.95);
+            if rng.gen::<f32>() >= survival {
+                break;
+            }
+            throughput = throughput * (1.0 / survival);
+        }
+
+        // start on the side of the surface the new direction goes to
+        let offset = if direction.dot(&normal) >= 0.0 { SECONDARY_RAY_EPSILON } else { -SECONDARY_RAY_EPSILON };
+        ray = Ray::new(hit.point + offset * normal, direction).at_time(ray.time);
+
+        match scene.intersect(&ray) {
+            Some((next_index, next_intersection)) => {
+                index = next_index;
+                intersection = next_intersection;
+            },
+            None => {
+                // after a diffuse bounce, the environment was also sampled directly
+                let weight = match pdf {
+                    Some(pdf) => {
+                        let light_pdf = settings.light_samples.max(1) as f32 * scene.environment.pdf(&normal, &direction);
+                        power_heuristic(pdf, light_pdf)
+                    },
+                    None => 1.0,
+                };
+                radiance += throughput * scene.environment.radiance(&direction) * weight;
+                break;
+            },
+        }
+    }
+
+    Some(radiance)
+}
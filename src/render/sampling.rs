@@ -0,0 +1,274 @@
+use std::f32::consts::PI;
+
+use rand::{Rng, RngCore};
+
+use crate::math::Vec3;
+
+/// How the sample positions are spread over a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplePattern {
+    /// Centers of the cells of a regular grid, a single sample goes through the center of the pixel.
+    #[default]
+    Grid,
+    /// One random position in each cell of a regular grid.
+    Stratified,
+    /// Randomly scrambled (0, 2) Sobol sequence: well spread positions, blue-noise like,
+    /// whatever the number of samples (best with powers of two).
+    Sobol,
+}
+
+impl SamplePattern {
+    /// `count` positions in the unit square.
+    pub fn generate(&self, count: u32, rng: &mut dyn RngCore) -> Vec<(f32, f32)> {
+        match self {
+            SamplePattern::Grid | SamplePattern::Stratified => {
+                let (columns, rows) = grid_size(count);
+                let jitter = *self == SamplePattern::Stratified;
+
+                (0..count).map(|i| {
+                    let (u, v) = if jitter { (rng.gen(), rng.gen()) } else { (0.5, 0.5) };
+                    (((i % columns) as f32 + u) / columns as f32, ((i / columns) as f32 + v) / rows as f32)
+                }).collect()
+            },
+            SamplePattern::Sobol => {
+                let scramble = (rng.next_u32(), rng.next_u32());
+                (0..count).map(|i| sobol_2d(i, scramble)).collect()
+            },
+        }
+    }
+}
+
+/// Columns and rows of the smallest, most square grid holding `count` cells.
+fn grid_size(count: u32) -> (u32, u32) {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    (columns, count.div_ceil(columns).max(1))
+}
+
+/// Point `index` of the first two dimensions of the Sobol sequence, scrambled by xoring
+/// the digits with `scramble` which keeps its stratification.
+pub fn sobol_2d(index: u32, scramble: (u32, u32)) -> (f32, f32) {
+    // the first dimension is the van der Corput sequence, the bits of the index in reverse order
+    let mut x = index.reverse_bits();
+
+    // the second one uses the generator matrix of the polynomial x + 1
+    let mut y = 0;
+    let mut direction = 1 << 31;
+    let mut bits = index;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            y ^= direction;
+        }
+        bits >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    x ^= scramble.0;
+    y ^= scramble.1;
+
+    (to_unit(x), to_unit(y))
+}
+
+/// Maps the 32 bits of `value` to [0, 1).
+fn to_unit(value: u32) -> f32 {
+    // keep the 24 bits a float can represent, so that rounding never gives 1.0
+    (value >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Two unit vectors which, along with `normal`, form an orthonormal basis.
+pub fn orthonormal_basis(normal: &Vec3) -> (Vec3, Vec3) {
+    // any vector not parallel to the normal will do
+    let helper = if normal.x.abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+
+    let tangent = helper.cross_product(normal).normalize();
+    let bitangent = normal.cross_product(&tangent);
+
+    (tangent, bitangent)
+}
+
+/// Maps two uniform numbers in [0, 1) to a point of the unit disk, preserving their stratification.
+pub fn concentric_disk(u1: f32, u2: f32) -> (f32, f32) {
+    let a = 2.0 * u1 - 1.0;
+    let b = 2.0 * u2 - 1.0;
+
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Direction of the hemisphere around `normal` picked with a density proportional to
+/// the cosine of its angle with the normal, that is `cos / PI`.
+pub fn cosine_hemisphere(normal: &Vec3, u1: f32, u2: f32) -> Vec3 {
+    let (x, y) = concentric_disk(u1, u2);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    let (tangent, bitangent) = orthonormal_basis(normal);
+
+    x * tangent + y * bitangent + z * *normal
+}
+
+/// Weight of a sample of density `pdf` combined with one drawn from another density,
+/// of value `other` for the same direction, by multiple importance sampling (power heuristic).
+pub fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other * other);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+/// Piecewise constant probability density over [0, 1), made of as many equal width bins
+/// as there are weights, each one proportional to its weight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    /// Cumulated weights, from 0 up to 1.
+    cdf: Vec<f32>,
+}
+
+impl Distribution {
+    /// Uniform when all the weights are zero.
+    pub fn new(weights: &[f32]) -> Distribution {
+        assert!(!weights.is_empty(), "empty distribution");
+
+        let total: f32 = weights.iter().map(|w| w.max(0.0)).sum();
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+
+        let mut sum = 0.0;
+        for (i, weight) in weights.iter().enumerate() {
+            sum += if total > 0.0 { weight.max(0.0) / total } else { 1.0 / weights.len() as f32 };
+            cdf.push(if i + 1 == weights.len() { 1.0 } else { sum });
+        }
+
+        Distribution { cdf }
+    }
+
+    fn bins(&self) -> usize {
+        self.cdf.len() - 1
+    }
+
+    fn bin_pdf(&self, bin: usize) -> f32 {
+        (self.cdf[bin + 1] - self.cdf[bin]) * self.bins() as f32
+    }
+
+    /// Position in [0, 1) picked by inverting the cumulative distribution at `u`,
+    /// along with its density.
+    pub fn sample(&self, u: f32) -> (f32, f32) {
+        // last bin whose cumulated weight is below u, skipping empty bins
+        let bin = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.bins() - 1);
+        let width = self.cdf[bin + 1] - self.cdf[bin];
+        let offset = if width > 0.0 { (u - self.cdf[bin]) / width } else { 0.5 };
+
+        let x = ((bin as f32 + offset.clamp(0.0, 1.0)) / self.bins() as f32).min(1.0 - f32::EPSILON);
+        (x, self.bin_pdf(bin))
+    }
+
+    /// Density at `x`, in [0, 1).
+    pub fn pdf(&self, x: f32) -> f32 {
+        let bin = ((x * self.bins() as f32) as usize).min(self.bins() - 1);
+        self.bin_pdf(bin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orthonormal_basis() {
+        for normal in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 3.0).normalize()] {
+            let (t, b) = orthonormal_basis(&normal);
+            assert!((t.norm() - 1.0).abs() < 1e-6 && (b.norm() - 1.0).abs() < 1e-6);
+            assert!(t.dot(&normal).abs() < 1e-6 && b.dot(&normal).abs() < 1e-6 && t.dot(&b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_cosine_hemisphere() {
+        let mut rng = rand::thread_rng();
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let count = 10000;
+        let mut mean_cos = 0.0;
+
+        for _ in 0..count {
+            let direction = cosine_hemisphere(&normal, rng.gen(), rng.gen());
+            assert!((direction.norm() - 1.0).abs() < 1e-5);
+            assert!(direction.dot(&normal) >= 0.0);
+            mean_cos += direction.dot(&normal) / count as f32;
+        }
+
+        // the expected cosine under a cos / PI density is 2 / 3
+        assert!((mean_cos - 2.0 / 3.0).abs() < 0.02, "mean cosine: {}", mean_cos);
+    }
+
+    #[test]
+    fn test_grid_pattern() {
+        let mut rng = rand::thread_rng();
+        assert_eq!(SamplePattern::Grid.generate(1, &mut rng), vec![(0.5, 0.5)]);
+        assert_eq!(
+            SamplePattern::Grid.generate(4, &mut rng),
+            vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)],
+        );
+        assert_eq!(SamplePattern::Grid.generate(5, &mut rng).len(), 5);
+    }
+
+    #[test]
+    fn test_stratified_pattern_has_one_sample_per_cell() {
+        let mut rng = rand::thread_rng();
+        let samples = SamplePattern::Stratified.generate(16, &mut rng);
+
+        let mut cells: Vec<(u32, u32)> = samples.iter().map(|&(u, v)| {
+            assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+            ((u * 4.0) as u32, (v * 4.0) as u32)
+        }).collect();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(cells.len(), 16);
+    }
+
+    #[test]
+    fn test_sobol_pattern_is_stratified() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..10 {
+            let samples = SamplePattern::Sobol.generate(16, &mut rng);
+
+            // 16 points of a (0, 2) sequence fall in distinct cells of any 16 cells grid of the unit square
+            for (columns, rows) in [(16, 1), (8, 2), (4, 4), (2, 8), (1, 16)] {
+                let mut cells: Vec<(u32, u32)> = samples.iter().map(|&(u, v)| {
+                    assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                    ((u * columns as f32) as u32, (v * rows as f32) as u32)
+                }).collect();
+                cells.sort();
+                cells.dedup();
+                assert_eq!(cells.len(), 16, "{} x {} grid", columns, rows);
+            }
+        }
+    }
+
+    #[test]
+    fn test_distribution() {
+        let distribution = Distribution::new(&[1.0, 0.0, 3.0]);
+
+        assert_eq!(distribution.pdf(0.1), 0.75);
+        assert_eq!(distribution.pdf(0.5), 0.0);
+        assert_eq!(distribution.pdf(0.9), 2.25);
+
+        // a quarter of the samples in the first bin, none in the empty one
+        let (x, pdf) = distribution.sample(0.125);
+        assert!((x - 1.0 / 6.0).abs() < 1e-6 && pdf == 0.75, "{} {}", x, pdf);
+        let (x, pdf) = distribution.sample(0.25);
+        assert!((2.0 / 3.0..1.0).contains(&x) && pdf == 2.25, "{} {}", x, pdf);
+        assert!(distribution.sample(0.999999).0 < 1.0);
+
+        let uniform = Distribution::new(&[0.0, 0.0]);
+        assert_eq!(uniform.sample(0.25), (0.25, 1.0));
+    }
+}
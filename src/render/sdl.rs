@@ -0,0 +1,47 @@
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use super::framebuffer::{Framebuffer, RenderTarget};
+use super::tonemap::ToneMapping;
+
+pub struct Display {
+    pub canvas: Canvas<Window>,
+    pub width: u32,
+    pub height: u32,
+    /// How the linear framebuffer is turned into screen colours.
+    pub tone_mapping: ToneMapping,
+}
+
+impl Display {
+    pub fn new(canvas: Canvas<Window>, width: u32, height: u32) -> Display {
+        Display { canvas, width, height, tone_mapping: ToneMapping::default() }
+    }
+
+    pub fn with_tone_mapping(self, tone_mapping: ToneMapping) -> Display {
+        Display { tone_mapping, ..self }
+    }
+}
+
+impl RenderTarget for Display {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn draw(&mut self, framebuffer: &Framebuffer) {
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, framebuffer.width, framebuffer.height)
+            .unwrap();
+
+        texture.update(None, &framebuffer.to_rgb8(&self.tone_mapping), framebuffer.width as usize * 3).unwrap();
+
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.canvas.copy(&texture, None, None).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::math::Vec3;
+
+use super::framebuffer::Framebuffer;
+use super::image;
+use super::objects::Color;
+use super::tonemap::srgb_decode;
+
+/// Colour varying over the surface of a shape.
+///
+/// Textures are evaluated at the texture coordinates `(u, v)` of the hit point,
+/// solid textures such as noise use its position in space instead.
+pub trait Texture: fmt::Debug + Send + Sync {
+    fn value(&self, u: f32, v: f32, point: &Vec3) -> Color;
+}
+
+fn lerp(a: Color, b: Color, t: f32) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+/// Same colour everywhere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constant(pub Color);
+
+impl Texture for Constant {
+    fn value(&self, _u: f32, _v: f32, _point: &Vec3) -> Color {
+        self.0
+    }
+}
+
+/// Checkerboard of `squares` by `squares` alternating colours over the unit square of texture space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Checker {
+    pub even: Color,
+    pub odd: Color,
+    pub squares: f32,
+}
+
+impl Checker {
+    pub fn new(even: Color, odd: Color, squares: f32) -> Checker {
+        Checker { even, odd, squares }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, _point: &Vec3) -> Color {
+        let parity = (u * self.squares).floor() + (v * self.squares).floor();
+
+        if parity.rem_euclid(2.0) < 1.0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// Linear blend from `start` to `end` along u, or along v when `vertical`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gradient {
+    pub start: Color,
+    pub end: Color,
+    pub vertical: bool,
+}
+
+impl Gradient {
+    pub fn horizontal(start: Color, end: Color) -> Gradient {
+        Gradient { start, end, vertical: false }
+    }
+
+    pub fn vertical(start: Color, end: Color) -> Gradient {
+        Gradient { start, end, vertical: true }
+    }
+}
+
+impl Texture for Gradient {
+    fn value(&self, u: f32, v: f32, _point: &Vec3) -> Color {
+        let t = if self.vertical { v } else { u };
+        lerp(self.start, self.end, t.clamp(0.0, 1.0))
+    }
+}
+
+/// Improved Perlin gradient noise.
+#[derive(Clone)]
+pub struct Perlin {
+    /// Shuffled 0..256, repeated twice to avoid wrapping the hashed indices.
+    permutation: Vec<u8>,
+}
+
+impl fmt::Debug for Perlin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Perlin").finish_non_exhaustive()
+    }
+}
+
+impl Perlin {
+    /// The same seed always gives the same noise.
+    pub fn new(seed: u64) -> Perlin {
+        let mut permutation: Vec<u8> = (0..=255).collect();
+        permutation.shuffle(&mut StdRng::seed_from_u64(seed));
+        permutation.extend_from_within(..);
+
+        Perlin { permutation }
+    }
+
+    fn hash(&self, x: usize, y: usize, z: usize) -> u8 {
+        let p = &self.permutation;
+        p[p[p[x] as usize + y] as usize + z]
+    }
+
+    /// Noise at `point`, in [-1, 1], zero at the points of integer coordinates.
+    pub fn noise(&self, point: &Vec3) -> f32 {
+        let cell = [point.x.floor(), point.y.floor(), point.z.floor()];
+        let [x, y, z] = [point.x - cell[0], point.y - cell[1], point.z - cell[2]];
+        let [i, j, k] = cell.map(|c| (c as i32 & 255) as usize);
+
+        // quintic smoothstep, for the noise to have continuous second derivatives
+        let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let [u, v, w] = [fade(x), fade(y), fade(z)];
+
+        // dot product of the offset to a corner with one of 12 gradients, picked by hashing the corner
+        let grad = |di: usize, dj: usize, dk: usize| {
+            let (x, y, z) = (x - di as f32, y - dj as f32, z - dk as f32);
+
+            match self.hash(i + di, j + dj, k + dk) & 15 {
+                0 | 12 => x + y,
+                1 | 14 => -x + y,
+                2 => x - y,
+                3 => -x - y,
+                4 => x + z,
+                5 => -x + z,
+                6 => x - z,
+                7 => -x - z,
+                8 => y + z,
+                9 | 13 => -y + z,
+                10 => y - z,
+                _ => -y - z,
+            }
+        };
+
+        let mix = |a: f32, b: f32, t: f32| a + t * (b - a);
+
+        mix(
+            mix(mix(grad(0, 0, 0), grad(1, 0, 0), u), mix(grad(0, 1, 0), grad(1, 1, 0), u), v),
+            mix(mix(grad(0, 0, 1), grad(1, 0, 1), u), mix(grad(0, 1, 1), grad(1, 1, 1), u), v),
+            w,
+        ).clamp(-1.0, 1.0)
+    }
+
+    /// Fractal Brownian motion: sum of `octaves` layers of noise, each of twice the frequency
+    /// and half the amplitude of the previous one, normalized to [-1, 1].
+    pub fn fbm(&self, point: &Vec3, octaves: u32) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(&(frequency * *point));
+            total += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+}
+
+/// Solid texture blending two colours according to fractal Perlin noise.
+#[derive(Debug, Clone)]
+pub struct Noise {
+    perlin: Perlin,
+    pub low: Color,
+    pub high: Color,
+    /// Frequency of the first octave, in features per unit of scene space.
+    pub scale: f32,
+    pub octaves: u32,
+}
+
+impl Noise {
+    pub fn new(low: Color, high: Color, scale: f32, octaves: u32) -> Noise {
+        Noise { perlin: Perlin::new(0), low, high, scale, octaves }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Noise {
+        Noise { perlin: Perlin::new(seed), ..self }
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, _u: f32, _v: f32, point: &Vec3) -> Color {
+        let noise = self.perlin.fbm(&(self.scale * *point), self.octaves);
+        lerp(self.low, self.high, 0.5 * (noise + 1.0))
+    }
+}
+
+/// Bilinearly filtered image, repeated over texture space.
+/// The v axis goes up, from the bottom row of the image.
+#[derive(Clone)]
+pub struct ImageTexture {
+    image: Framebuffer,
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("width", &self.image.width)
+            .field("height", &self.image.height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ImageTexture {
+    pub fn new(image: Framebuffer) -> ImageTexture {
+        assert!(image.width > 0 && image.height > 0, "empty texture image");
+        ImageTexture { image }
+    }
+
+    /// Loads a PPM or PNG image of sRGB encoded colours, converted to linear ones.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ImageTexture> {
+        let mut texture = ImageTexture::load_linear(path)?;
+
+        for pixel in &mut texture.image.pixels {
+            for c in &mut pixel[..3] {
+                *c = srgb_decode(*c);
+            }
+        }
+
+        Ok(texture)
+    }
+
+    /// Loads a PPM or PNG image holding data rather than colours, such as a normal map,
+    /// keeping its values as they are.
+    pub fn load_linear<P: AsRef<Path>>(path: P) -> io::Result<ImageTexture> {
+        let image = image::load(path)?;
+
+        if image.width == 0 || image.height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty texture image"));
+        }
+
+        Ok(ImageTexture::new(image))
+    }
+
+    pub fn image(&self) -> &Framebuffer {
+        &self.image
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.image.width as i64) as u32;
+        let y = y.rem_euclid(self.image.height as i64) as u32;
+        self.image.color(x, y)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _point: &Vec3) -> Color {
+        // texel centers are at half integer coordinates
+        let x = u * self.image.width as f32 - 0.5;
+        let y = (1.0 - v) * self.image.height as f32 - 0.5;
+
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        lerp(
+            lerp(self.texel(x0, y0), self.texel(x0 + 1, y0), tx),
+            lerp(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), tx),
+            ty,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: Vec3 = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+
+    fn assert_color_eq(a: Color, b: Color) {
+        assert!((a.rgb - b.rgb).norm() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_checker() {
+        let black = Color::black();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let checker = Checker::new(white, black, 4.0);
+
+        assert_eq!(checker.value(0.1, 0.1, &ORIGIN), white);
+        assert_eq!(checker.value(0.3, 0.1, &ORIGIN), black);
+        assert_eq!(checker.value(0.3, 0.3, &ORIGIN), white);
+        // repeats outside of the unit square
+        assert_eq!(checker.value(-0.1, 0.1, &ORIGIN), black);
+    }
+
+    #[test]
+    fn test_gradient() {
+        let gradient = Gradient::vertical(Color::black(), Color::new(1.0, 0.5, 0.0));
+
+        assert_color_eq(gradient.value(0.9, 0.5, &ORIGIN), Color::new(0.5, 0.25, 0.0));
+        assert_color_eq(gradient.value(0.0, 2.0, &ORIGIN), Color::new(1.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn test_perlin_noise() {
+        let perlin = Perlin::new(7);
+
+        assert_eq!(perlin.noise(&Vec3::new(3.0, -2.0, 5.0)), 0.0);
+
+        let point = Vec3::new(0.3, 1.7, -4.2);
+        assert_eq!(perlin.noise(&point), Perlin::new(7).noise(&point));
+
+        let mut varies = false;
+        for i in 0..100 {
+            let point = Vec3::new(i as f32 * 0.37, i as f32 * 0.11, 0.5);
+            let noise = perlin.noise(&point);
+            let fbm = perlin.fbm(&point, 5);
+            assert!((-1.0..=1.0).contains(&noise) && (-1.0..=1.0).contains(&fbm));
+            varies |= noise.abs() > 0.1;
+
+            // continuous
+            let next = perlin.noise(&(point + Vec3::new(1e-3, 0.0, 0.0)));
+            assert!((next - noise).abs() < 0.01);
+        }
+        assert!(varies);
+    }
+
+    #[test]
+    fn test_image_texture() {
+        let mut image = Framebuffer::new(2, 2);
+        image.set_color(0, 0, Color::new(1.0, 0.0, 0.0));
+        image.set_color(1, 0, Color::new(0.0, 1.0, 0.0));
+        image.set_color(0, 1, Color::new(0.0, 0.0, 1.0));
+        image.set_color(1, 1, Color::new(1.0, 1.0, 1.0));
+        let texture = ImageTexture::new(image);
+
+        // texel centers: v goes up from the bottom row
+        assert_color_eq(texture.value(0.25, 0.75, &ORIGIN), Color::new(1.0, 0.0, 0.0));
+        assert_color_eq(texture.value(0.75, 0.25, &ORIGIN), Color::new(1.0, 1.0, 1.0));
+        // halfway between the two top texels
+        assert_color_eq(texture.value(0.5, 0.75, &ORIGIN), Color::new(0.5, 0.5, 0.0));
+        // wraps around
+        assert_color_eq(texture.value(1.25, -0.25, &ORIGIN), Color::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_load_decodes_srgb() {
+        let path = std::env::temp_dir().join(format!("texture-{}.ppm", std::process::id()));
+        std::fs::write(&path, b"P6\n1 1\n255\n\xbc\x00\xff").unwrap();
+
+        let linear = ImageTexture::load_linear(&path).unwrap().value(0.5, 0.5, &ORIGIN);
+        let decoded = ImageTexture::load(&path).unwrap().value(0.5, 0.5, &ORIGIN);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_color_eq(linear, Color::new(188.0 / 255.0, 0.0, 1.0));
+        assert!((decoded.rgb.x - 0.5).abs() < 0.01, "{:?}", decoded);
+        assert_eq!((decoded.rgb.y, decoded.rgb.z), (0.0, 1.0));
+    }
+}
@@ -0,0 +1,126 @@
+use super::objects::Color;
+
+/// Curve bringing linear radiance, unbounded, into the [0, 1] range of a display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Operator {
+    /// Cuts everything above 1, bright areas burn out to flat colours.
+    #[default]
+    Clamp,
+    /// `c / (1 + c)` per channel: never saturates, but greys out highlights.
+    Reinhard,
+    /// Filmic curve of the ACES reference rendering transform, fitted by Krzysztof Narkowicz:
+    /// a toe deepening the shadows and a soft shoulder for the highlights.
+    Aces,
+}
+
+impl Operator {
+    pub fn apply(&self, c: f32) -> f32 {
+        let c = c.max(0.0);
+
+        match self {
+            Operator::Clamp => c.min(1.0),
+            Operator::Reinhard => c / (1.0 + c),
+            Operator::Aces => {
+                let (a, b, c2, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                (c * (a * c + b) / (c * (c2 * c + d) + e)).clamp(0.0, 1.0)
+            },
+        }
+    }
+}
+
+/// Conversion of the linear colours of the framebuffer to the 8 bits sRGB values of a screen
+/// or an image file: scaled by the exposure, tone mapped then gamma encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ToneMapping {
+    pub operator: Operator,
+    /// In stops, each one doubling the brightness.
+    pub exposure: f32,
+}
+
+impl ToneMapping {
+    pub fn new(operator: Operator) -> ToneMapping {
+        ToneMapping { operator, exposure: 0.0 }
+    }
+
+    pub fn with_exposure(self, exposure: f32) -> ToneMapping {
+        ToneMapping { exposure, ..self }
+    }
+
+    /// Displayed colour, still linear, in [0, 1].
+    pub fn apply(&self, color: Color) -> Color {
+        let scale = self.exposure.exp2();
+        let [r, g, b] = [color.rgb.x, color.rgb.y, color.rgb.z].map(|c| self.operator.apply(c * scale));
+        Color::new(r, g, b)
+    }
+
+    pub fn to_srgb8(&self, color: Color) -> [u8; 3] {
+        let color = self.apply(color);
+        [color.rgb.x, color.rgb.y, color.rgb.z].map(|c| (srgb_encode(c) * 255.0).round() as u8)
+    }
+}
+
+/// sRGB transfer function, from linear light to the value stored in an image.
+pub fn srgb_encode(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `srgb_encode`, from the value stored in an image to linear light.
+pub fn srgb_decode(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_round_trip() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+        // mid grey is about 0.73 once encoded
+        assert!((srgb_encode(0.5) - 0.7354).abs() < 1e-3);
+
+        for i in 0..=20 {
+            let c = i as f32 / 20.0;
+            assert!((srgb_decode(srgb_encode(c)) - c).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_operators() {
+        for operator in [Operator::Clamp, Operator::Reinhard, Operator::Aces] {
+            assert_eq!(operator.apply(0.0), 0.0);
+            assert_eq!(operator.apply(-1.0), 0.0);
+
+            // increasing, bounded by 1
+            let mut previous = 0.0;
+            for i in 1..100 {
+                let c = operator.apply(i as f32 * 0.25);
+                assert!(c >= previous && c <= 1.0, "{:?}", operator);
+                previous = c;
+            }
+        }
+
+        assert_eq!(Operator::Clamp.apply(4.0), 1.0);
+        assert_eq!(Operator::Reinhard.apply(1.0), 0.5);
+        assert!(Operator::Reinhard.apply(100.0) < 1.0);
+        assert!(Operator::Aces.apply(100.0) > 0.99);
+    }
+
+    #[test]
+    fn test_exposure() {
+        let tone_mapping = ToneMapping::default().with_exposure(1.0);
+        assert_eq!(tone_mapping.apply(Color::new(0.25, 0.5, 2.0)), Color::new(0.5, 1.0, 1.0));
+
+        let tone_mapping = ToneMapping::default().with_exposure(-1.0);
+        assert_eq!(tone_mapping.to_srgb8(Color::new(2.0, 0.0, 0.0)), [255, 0, 0]);
+    }
+}